@@ -100,7 +100,7 @@ impl Block {
         if difficulty <= 1 {
             return 1;
         }
-        return if time_diff < MINE_RATE as i64 {
+        if time_diff < MINE_RATE as i64 {
             difficulty + 1
         } else {
             difficulty - 1
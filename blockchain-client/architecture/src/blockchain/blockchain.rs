@@ -1,31 +1,72 @@
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
 use serde::ser::SerializeStruct;
 use serde::{Deserialize, Serialize};
+use serde_json::json;
 use crate::blockchain::block::Block;
-use crate::wallet::transaction::Transaction;
+use crate::utils::config::MINING_REWARD;
+use crate::utils::utils::crypto_hash;
+use crate::wallet::transaction::{Transaction, TRANSACTION_VERSION};
+
+pub const DEFAULT_CHAIN_ID: u32 = 1;
 
 #[derive(Clone, Debug, Deserialize)]
 pub struct Blockchain {
     pub chain: Vec<Block>,
+    #[serde(skip, default = "default_chain_id")]
+    pub chain_id: u32,
+}
+
+/// Why a received chain was refused. One that is merely not longer than ours is not an error.
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+#[serde(tag = "code", rename_all = "snake_case")]
+pub enum ChainError {
+    Invalid,
+    ForeignTransactions,
+}
+
+fn default_chain_id() -> u32 {
+    DEFAULT_CHAIN_ID
+}
+
+impl Default for Blockchain {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Blockchain {
     pub fn new() -> Blockchain {
+        Blockchain::with_chain_id(DEFAULT_CHAIN_ID)
+    }
+
+    pub fn with_chain_id(chain_id: u32) -> Blockchain {
         Blockchain {
             chain: vec![Block::genesis()],
+            chain_id,
         }
     }
+
+    /// Identifies the network: nodes only talk to each other when they build on the same genesis block
+    /// with the same chain id.
+    pub fn network_id(chain_id: u32) -> String {
+        crypto_hash(&[json!(Block::genesis().hash), json!(chain_id)])[..16].to_string()
+    }
     
     pub fn add_block(&mut self, data: Vec<Transaction>) -> Block {
         let last_block = self.chain.last().unwrap();
-        let new_block = Block::mine_block(&last_block, data);
+        let new_block = Block::mine_block(last_block, data);
         self.chain.push(new_block.clone());
         new_block
     }
     
-    pub fn is_valid_chain(chain: &Vec<Block>) -> bool {
+    pub fn is_valid_chain(chain: &[Block]) -> bool {
         if chain[0] != Block::genesis() {
             return false;
         }
+        // every address signs with strictly increasing nonces, so no transaction can be included twice
+        let mut last_nonces: HashMap<&str, u64> = HashMap::new();
         
         for i in 1..chain.len() {
             let block = &chain[i];
@@ -34,25 +75,95 @@ impl Blockchain {
             if block.last_hash != last_block.hash || block.hash != Block::block_hash(block){
                 return false;
             }
+
+            if !block.data.iter().all(Transaction::has_valid_id) || !Blockchain::has_valid_reward(block) {
+                return false;
+            }
+            // legacy transactions do not sign their input amount and timestamp, so a miner could alter both
+            if block.data.iter().any(|transaction| transaction.version < TRANSACTION_VERSION) {
+                return false;
+            }
+            // rewards are signed by the blockchain wallet, which has no real key
+            if !block.data.iter().filter(|transaction| !transaction.is_reward()).all(|transaction| transaction.verify().is_ok()) {
+                return false;
+            }
+            for transaction in block.data.iter().filter(|transaction| !transaction.is_reward()) {
+                let Some(input) = &transaction.input else {
+                    continue;
+                };
+                let last_nonce = last_nonces.entry(input.address.as_str()).or_insert(0);
+                if input.nonce <= *last_nonce {
+                    return false;
+                }
+                *last_nonce = input.nonce;
+            }
         }
         
         true
     }
+
+    /// A block mints exactly one reward, paying the mining reward plus the fees its other transactions leave.
+    fn has_valid_reward(block: &Block) -> bool {
+        let (rewards, transactions): (Vec<&Transaction>, Vec<&Transaction>) = block.data.iter().partition(|transaction| transaction.is_reward());
+        let [reward] = rewards[..] else {
+            return false;
+        };
+        let expected = transactions.iter().try_fold(MINING_REWARD, |total, transaction| total.checked_add(transaction.fee()));
+        expected.is_some() && reward.output_total() == expected
+    }
+
+    /// Highest nonce the address has used on the chain, 0 if it never sent a transaction.
+    pub fn last_nonce(&self, address: &str) -> u64 {
+        self.chain.iter()
+            .flat_map(|block| block.data.iter())
+            .filter(|transaction| !transaction.is_reward())
+            .filter_map(|transaction| transaction.input.as_ref())
+            .filter(|input| input.address == address)
+            .map(|input| input.nonce)
+            .max()
+            .unwrap_or(0)
+    }
+
+    /// The blocks of this chain that replacing it with `new_chain` would disconnect, those after the last common block.
+    pub fn disconnected_by(&self, new_chain: &[Block]) -> Vec<Block> {
+        let common = self.chain.iter().zip(new_chain).take_while(|(block, new_block)| block.hash == new_block.hash).count();
+        self.chain[common..].to_vec()
+    }
+
+    fn belongs_to_chain(&self, chain: &[Block]) -> bool {
+        chain.iter()
+            .flat_map(|block| block.data.iter())
+            .all(|transaction| transaction.chain_id() == Some(self.chain_id))
+    }
     
-    pub fn replace_chain(&mut self, new_chain: Vec<Block>) {
+    /// Returns whether the chain was replaced, and an error if the new chain could never be valid.
+    pub fn replace_chain(&mut self, new_chain: Vec<Block>) -> Result<bool, ChainError> {
         if new_chain.len() <= self.chain.len() {
             println!("Received chain is not longer than the current chain.");
-            return;
+            return Ok(false);
         } else if !Blockchain::is_valid_chain(&new_chain) {
-            println!("Received chain is not valid.");
-            return;
+            return Err(ChainError::Invalid);
+        } else if !self.belongs_to_chain(&new_chain) {
+            return Err(ChainError::ForeignTransactions);
         }
         
         println!("Replacing blockchain with the new chain.");
         self.chain = new_chain;
+        Ok(true)
+    }
+}
+
+impl fmt::Display for ChainError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ChainError::Invalid => write!(f, "received chain is not valid"),
+            ChainError::ForeignTransactions => write!(f, "received chain contains transactions signed for another chain"),
+        }
     }
 }
 
+impl Error for ChainError {}
+
 impl Serialize for Blockchain {
     fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error> where S: serde::Serializer {
         let mut state = serializer.serialize_struct("Blockchain", 1)?;
@@ -1,2 +1,3 @@
 pub mod block;
+#[allow(clippy::module_inception)]
 pub mod blockchain;
\ No newline at end of file
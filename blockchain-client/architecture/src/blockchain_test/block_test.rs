@@ -18,7 +18,6 @@ fn test_block() {
 fn test_genesis_block() {
     let last_hash = String::from("genesis_last_hash");
     let hash = String::from("genesis_hash");
-    let data = String::from("genesis_data");
     let nonce = 0;
     let difficulty = DIFFICULTY;
 
@@ -1,6 +1,8 @@
-use crate::blockchain::blockchain::{Blockchain};
-use crate::utils::config::DIFFICULTY;
-use crate::wallet::transaction::Transaction;
+use chrono::Utc;
+use crate::blockchain::block::Block;
+use crate::blockchain::blockchain::{Blockchain, ChainError};
+use crate::utils::config::{DIFFICULTY, MINING_REWARD};
+use crate::wallet::transaction::{Transaction, UnsignedTransaction, LEGACY_TRANSACTION_VERSION};
 use crate::wallet::wallet::Wallet;
 
 #[test]
@@ -25,15 +27,15 @@ fn test_add_block() {
 #[test]
 fn test_validates_a_valid_chain() {
     let mut blockchain = Blockchain::new();
-    blockchain.add_block(vec![]);
-    assert_eq!(Blockchain::is_valid_chain(&blockchain.chain), true);
+    blockchain.add_block(vec![Transaction::reward_transaction(&Wallet::new(), &Wallet::blockchain_wallet())]);
+    assert!(Blockchain::is_valid_chain(&blockchain.chain));
 }
 
 #[test]
 fn test_invalidates_a_chain_with_a_corrupt_genesis_block() {
     let mut blockchain = Blockchain::new();
     blockchain.chain[0].data = vec![Transaction::reward_transaction(&Wallet::new(), &Wallet::blockchain_wallet())];
-    assert_eq!(Blockchain::is_valid_chain(&blockchain.chain), false);
+    assert!(!Blockchain::is_valid_chain(&blockchain.chain));
 }
 
 #[test]
@@ -41,7 +43,7 @@ fn test_invalidates_a_corrupt_chain() {
     let mut blockchain = Blockchain::new();
     blockchain.add_block(vec![]);
     blockchain.chain[1].data = vec![Transaction::reward_transaction(&Wallet::new(), &Wallet::blockchain_wallet())];
-    assert_eq!(Blockchain::is_valid_chain(&blockchain.chain), false);
+    assert!(!Blockchain::is_valid_chain(&blockchain.chain));
 }
 
 #[test]
@@ -49,7 +51,7 @@ fn test_replaces_chain_with_a_valid_chain(){
     let mut blockchain = Blockchain::new();
     let mut blockchain2 = Blockchain::new();
     blockchain2.add_block(vec![Transaction::reward_transaction(&Wallet::new(), &Wallet::blockchain_wallet())]);
-    blockchain.replace_chain(blockchain2.chain.clone());
+    assert_eq!(blockchain.replace_chain(blockchain2.chain.clone()), Ok(true));
     assert_eq!(blockchain.chain, blockchain2.chain);
 }
 
@@ -58,6 +60,124 @@ fn test_does_not_replace_chain_with_one_of_equal_or_less_length(){
     let mut blockchain = Blockchain::new();
     let blockchain2 = Blockchain::new();
     blockchain.add_block(vec![Transaction::reward_transaction(&Wallet::new(), &Wallet::blockchain_wallet())]);
-    blockchain.replace_chain(blockchain2.chain.clone());
+    assert_eq!(blockchain.replace_chain(blockchain2.chain.clone()), Ok(false));
+    assert_ne!(blockchain.chain, blockchain2.chain);
+}
+
+#[test]
+fn test_does_not_replace_chain_with_transactions_from_another_chain(){
+    let mut blockchain = Blockchain::new();
+    let mut blockchain2 = Blockchain::with_chain_id(blockchain.chain_id + 1);
+    let mut blockchain_wallet = Wallet::blockchain_wallet();
+    blockchain_wallet.chain_id = blockchain2.chain_id;
+    blockchain2.add_block(vec![Transaction::reward_transaction(&Wallet::new(), &blockchain_wallet)]);
+    assert_eq!(blockchain.replace_chain(blockchain2.chain.clone()), Err(ChainError::ForeignTransactions));
+    assert_ne!(blockchain.chain, blockchain2.chain);
+}
+
+#[test]
+fn test_refuses_to_replace_chain_with_an_invalid_chain(){
+    let mut blockchain = Blockchain::new();
+    let mut blockchain2 = Blockchain::new();
+    blockchain2.add_block(vec![]);
+    blockchain2.chain[1].data = vec![Transaction::reward_transaction(&Wallet::new(), &Wallet::blockchain_wallet())];
+    assert_eq!(blockchain.replace_chain(blockchain2.chain.clone()), Err(ChainError::Invalid));
     assert_ne!(blockchain.chain, blockchain2.chain);
 }
+
+#[test]
+fn test_network_id_depends_on_the_chain_id() {
+    assert_eq!(Blockchain::network_id(1), Blockchain::network_id(1));
+    assert_ne!(Blockchain::network_id(1), Blockchain::network_id(2));
+}
+
+// skips the proof of work, which chain validation does not check
+fn append_block(blockchain: &mut Blockchain, data: Vec<Transaction>) {
+    let mut block = Block::new(Utc::now(), blockchain.chain.last().unwrap().hash.clone(), String::new(), data, 0, 1);
+    block.hash = Block::block_hash(&block);
+    blockchain.chain.push(block);
+}
+
+// pays the miner what the block's transactions leave as fees
+fn rewarded(mut data: Vec<Transaction>) -> Vec<Transaction> {
+    let fees = data.iter().map(Transaction::fee).sum();
+    data.push(Transaction::reward_transaction_with_fees(&Wallet::new(), &Wallet::blockchain_wallet(), fees));
+    data
+}
+
+#[test]
+fn test_validates_a_chain_with_increasing_nonces() {
+    let mut wallet = Wallet::new();
+    let mut blockchain = Blockchain::new();
+    append_block(&mut blockchain, rewarded(vec![Transaction::new(&wallet, "recipient".to_string(), 50).unwrap()]));
+    wallet.nonce = 2;
+    append_block(&mut blockchain, rewarded(vec![Transaction::new(&wallet, "recipient".to_string(), 50).unwrap()]));
+    assert!(Blockchain::is_valid_chain(&blockchain.chain));
+    assert_eq!(blockchain.last_nonce(&wallet.public_key), 2);
+}
+
+#[test]
+fn test_invalidates_a_chain_that_replays_a_transaction() {
+    let wallet = Wallet::new();
+    let transaction = Transaction::new(&wallet, "recipient".to_string(), 50).unwrap();
+    let mut blockchain = Blockchain::new();
+    append_block(&mut blockchain, rewarded(vec![transaction.clone()]));
+    append_block(&mut blockchain, rewarded(vec![transaction]));
+    assert!(!Blockchain::is_valid_chain(&blockchain.chain));
+}
+
+#[test]
+fn test_invalidates_a_chain_with_a_forged_signature() {
+    let mut transaction = Transaction::new(&Wallet::new(), "recipient".to_string(), 50).unwrap();
+    transaction.outputs[1].address = "attacker".to_string();
+    transaction.id = transaction.compute_id();
+    let mut blockchain = Blockchain::new();
+    append_block(&mut blockchain, rewarded(vec![transaction]));
+    assert!(!Blockchain::is_valid_chain(&blockchain.chain));
+}
+
+#[test]
+fn test_validates_a_reward_that_collects_the_fees() {
+    let mut blockchain = Blockchain::new();
+    let wallet = Wallet::new();
+    let mut unsigned = UnsignedTransaction::payment(&wallet.public_key, wallet.balance, wallet.chain_id, 1, Wallet::new().public_key, 50).unwrap();
+    unsigned.outputs[0].amount -= 3;
+    append_block(&mut blockchain, rewarded(vec![unsigned.sign(&wallet).unwrap()]));
+    assert_eq!(blockchain.chain[1].data[1].outputs[0].amount, MINING_REWARD + 3);
+    assert!(Blockchain::is_valid_chain(&blockchain.chain));
+}
+
+#[test]
+fn test_invalidates_a_chain_with_an_inflated_reward() {
+    let mut blockchain = Blockchain::new();
+    append_block(&mut blockchain, vec![Transaction::reward_transaction_with_fees(&Wallet::new(), &Wallet::blockchain_wallet(), 1000)]);
+    assert!(!Blockchain::is_valid_chain(&blockchain.chain));
+}
+
+#[test]
+fn test_invalidates_a_chain_with_a_missing_or_duplicated_reward() {
+    let mut blockchain = Blockchain::new();
+    append_block(&mut blockchain, vec![]);
+    assert!(!Blockchain::is_valid_chain(&blockchain.chain));
+
+    let mut blockchain = Blockchain::new();
+    let reward = Transaction::reward_transaction(&Wallet::new(), &Wallet::blockchain_wallet());
+    append_block(&mut blockchain, vec![reward.clone(), reward]);
+    assert!(!Blockchain::is_valid_chain(&blockchain.chain));
+}
+
+#[test]
+fn test_invalidates_a_chain_with_legacy_transactions() {
+    let wallet = Wallet::new();
+    let mut transaction = Transaction::new(&wallet, Wallet::new().public_key, 50).unwrap();
+    transaction.version = LEGACY_TRANSACTION_VERSION;
+    transaction.input.as_mut().unwrap().signature = wallet.sign(&transaction.sighash().unwrap());
+    // the legacy signature does not cover the input amount, so a miner could raise it to pay itself a fee
+    transaction.input.as_mut().unwrap().amount += 1000;
+    transaction.id = transaction.compute_id();
+    assert_eq!(transaction.verify(), Ok(()));
+
+    let mut blockchain = Blockchain::new();
+    append_block(&mut blockchain, rewarded(vec![transaction]));
+    assert!(!Blockchain::is_valid_chain(&blockchain.chain));
+}
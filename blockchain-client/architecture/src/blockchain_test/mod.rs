@@ -1,5 +1,6 @@
 #[cfg(test)]
 pub mod block_test;
 #[cfg(test)]
+#[allow(clippy::module_inception)]
 pub mod blockchain_test;
 
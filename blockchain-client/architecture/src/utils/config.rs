@@ -1,4 +1,8 @@
 pub(crate) const DIFFICULTY: u64 = 3;
 pub(crate) const MINE_RATE: u64 = 10;
 pub(crate) const INITIAL_BALANCE: u64 = 500;
-pub(crate) const MINING_REWARD: u64 = 50;
\ No newline at end of file
+pub(crate) const MINING_REWARD: u64 = 50;
+pub(crate) const MEMPOOL_MAX_TRANSACTIONS: usize = 5000;
+pub(crate) const MEMPOOL_MAX_BYTES: usize = 5 * 1024 * 1024;
+pub(crate) const MEMPOOL_EXPIRY_SECS: i64 = 24 * 60 * 60;
+pub(crate) const REPLACEMENT_FEE_INCREMENT: u64 = 1;
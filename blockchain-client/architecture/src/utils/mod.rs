@@ -1,2 +1,3 @@
+#[allow(clippy::module_inception)]
 pub mod utils;
 pub mod config;
\ No newline at end of file
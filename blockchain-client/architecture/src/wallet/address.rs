@@ -0,0 +1,92 @@
+use std::error::Error;
+use std::fmt;
+use bech32::primitives::decode::CheckedHrpstring;
+use bech32::{Bech32m, Hrp};
+use k256::ecdsa::VerifyingKey;
+use serde::{Serialize, Serializer};
+use crate::blockchain::blockchain::DEFAULT_CHAIN_ID;
+
+/// Human-readable prefix of addresses on the default chain.
+pub const MAIN_PREFIX: &str = "rc";
+/// Prefix on every other chain, so test funds are never mistaken for real ones.
+pub const TEST_PREFIX: &str = "rct";
+
+/// A bech32m encoded, checksummed address: the network prefix followed by the compressed SEC1 public key.
+/// The chain still records the key as hex, this is the form users type and copy around.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Address {
+    prefix: &'static str,
+    public_key: Vec<u8>,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+#[serde(tag = "code", rename_all = "snake_case")]
+pub enum AddressError {
+    InvalidEncoding { message: String },
+    WrongNetwork { expected: String, found: String },
+    InvalidPublicKey,
+}
+
+impl Address {
+    pub fn prefix(chain_id: u32) -> &'static str {
+        if chain_id == DEFAULT_CHAIN_ID { MAIN_PREFIX } else { TEST_PREFIX }
+    }
+
+    pub fn from_public_key(public_key: &VerifyingKey, chain_id: u32) -> Address {
+        Address {
+            prefix: Address::prefix(chain_id),
+            public_key: public_key.to_sec1_bytes().to_vec(),
+        }
+    }
+
+    /// Converts the hex public key the chain uses as an address.
+    pub fn from_public_key_hex(public_key: &str, chain_id: u32) -> Result<Address, AddressError> {
+        let public_key = hex::decode(public_key).map_err(|_| AddressError::InvalidPublicKey)?;
+        let public_key = VerifyingKey::from_sec1_bytes(&public_key).map_err(|_| AddressError::InvalidPublicKey)?;
+        Ok(Address::from_public_key(&public_key, chain_id))
+    }
+
+    /// Parses an address, which has to carry the prefix of the given chain and a valid public key.
+    pub fn parse(address: &str, chain_id: u32) -> Result<Address, AddressError> {
+        let checked = CheckedHrpstring::new::<Bech32m>(address.trim())
+            .map_err(|e| AddressError::InvalidEncoding { message: e.to_string() })?;
+        let prefix = Address::prefix(chain_id);
+        let found = checked.hrp().to_lowercase();
+        if found != prefix {
+            return Err(AddressError::WrongNetwork { expected: prefix.to_string(), found });
+        }
+        let public_key = checked.byte_iter().collect::<Vec<u8>>();
+        VerifyingKey::from_sec1_bytes(&public_key).map_err(|_| AddressError::InvalidPublicKey)?;
+        Ok(Address { prefix, public_key })
+    }
+
+    /// The hex public key this address stands for in transaction inputs and outputs.
+    pub fn public_key_hex(&self) -> String {
+        hex::encode(&self.public_key)
+    }
+}
+
+impl fmt::Display for Address {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let hrp = Hrp::parse(self.prefix).unwrap();
+        write!(f, "{}", bech32::encode::<Bech32m>(hrp, &self.public_key).unwrap())
+    }
+}
+
+impl Serialize for Address {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl fmt::Display for AddressError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            AddressError::InvalidEncoding { message } => write!(f, "not a bech32m address: {message}"),
+            AddressError::WrongNetwork { expected, found } => write!(f, "address is for network '{found}', expected '{expected}'"),
+            AddressError::InvalidPublicKey => write!(f, "address does not hold a secp256k1 public key"),
+        }
+    }
+}
+
+impl Error for AddressError {}
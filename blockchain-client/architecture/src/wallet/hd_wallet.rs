@@ -0,0 +1,150 @@
+use std::error::Error;
+use std::fmt;
+use bip32::{ChildNumber, DerivationPath, Language, Mnemonic, XPrv};
+use serde::Serialize;
+use crate::blockchain::blockchain::{Blockchain, DEFAULT_CHAIN_ID};
+use crate::wallet::transaction_pool::TransactionPool;
+use crate::wallet::wallet::{BalanceReport, Wallet};
+
+/// BIP-44 account path the addresses are derived under, using the coin type reserved for test networks.
+pub const ACCOUNT_PATH: &str = "m/44'/1'/0'/0";
+/// Recovery stops after this many consecutive addresses without any transaction on the chain.
+pub const GAP_LIMIT: u32 = 20;
+/// Length of the mnemonic's entropy, 24 words.
+pub const ENTROPY_LEN: usize = 32;
+
+/// Derives any number of wallets from one BIP-39 mnemonic, so backing up the phrase once is enough to
+/// restore every address. Address `i` is the key at `ACCOUNT_PATH/i`.
+#[derive(Clone)]
+pub struct HdWallet {
+    mnemonic: Mnemonic,
+    passphrase: String,
+    account: XPrv,
+    /// Number of addresses handed out so far, the next one gets this index.
+    pub address_count: u32,
+    pub chain_id: u32,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+#[serde(tag = "code", rename_all = "snake_case")]
+pub enum HdWalletError {
+    InvalidMnemonic,
+    InvalidSeed,
+}
+
+impl HdWallet {
+    pub fn generate() -> HdWallet {
+        HdWallet::from_mnemonic(Mnemonic::random(rand::thread_rng(), Language::English), "")
+    }
+
+    /// Restores the wallet from its 24 word phrase. An optional passphrase yields an entirely different set of keys.
+    pub fn from_phrase(phrase: &str, passphrase: &str) -> Result<HdWallet, HdWalletError> {
+        let mnemonic = Mnemonic::new(phrase.trim(), Language::English).map_err(|_| HdWalletError::InvalidMnemonic)?;
+        Ok(HdWallet::from_mnemonic(mnemonic, passphrase))
+    }
+
+    /// Restores the wallet from what `seed` returned, as stored in a keystore.
+    pub fn from_seed(seed: &[u8]) -> Result<HdWallet, HdWalletError> {
+        if seed.len() < ENTROPY_LEN {
+            return Err(HdWalletError::InvalidSeed);
+        }
+        let (entropy, passphrase) = seed.split_at(ENTROPY_LEN);
+        let passphrase = std::str::from_utf8(passphrase).map_err(|_| HdWalletError::InvalidSeed)?;
+        Ok(HdWallet::from_mnemonic(Mnemonic::from_entropy(entropy.try_into().unwrap(), Language::English), passphrase))
+    }
+
+    /// Restores the wallet and hands out every address up to the last one used on the chain.
+    pub fn recover(phrase: &str, passphrase: &str, blockchain: &Blockchain) -> Result<HdWallet, HdWalletError> {
+        let mut hd_wallet = HdWallet::from_phrase(phrase, passphrase)?;
+        let mut unused = 0;
+        let mut index = 0;
+        while unused < GAP_LIMIT {
+            if is_used(&hd_wallet.derive(index).public_key, blockchain) {
+                hd_wallet.address_count = index + 1;
+                unused = 0;
+            } else {
+                unused += 1;
+            }
+            index += 1;
+        }
+        Ok(hd_wallet)
+    }
+
+    fn from_mnemonic(mnemonic: Mnemonic, passphrase: &str) -> HdWallet {
+        let path: DerivationPath = ACCOUNT_PATH.parse().unwrap();
+        let account = XPrv::derive_from_path(mnemonic.to_seed(passphrase), &path)
+            .expect("a 64 byte seed always derives");
+        HdWallet {
+            mnemonic,
+            passphrase: passphrase.to_string(),
+            account,
+            address_count: 0,
+            chain_id: DEFAULT_CHAIN_ID,
+        }
+    }
+
+    /// The backup phrase. Whoever knows it, and the passphrase if any, controls every derived address.
+    pub fn phrase(&self) -> &str {
+        self.mnemonic.phrase()
+    }
+
+    /// The mnemonic's entropy followed by the passphrase, everything needed to derive the keys again.
+    pub fn seed(&self) -> Vec<u8> {
+        [self.mnemonic.entropy().as_slice(), self.passphrase.as_bytes()].concat()
+    }
+
+    pub fn derive(&self, index: u32) -> Wallet {
+        let child = ChildNumber::new(index, false).expect("index is below the hardened range");
+        let key = self.account.derive_child(child).expect("derivation only fails with negligible probability");
+        let mut wallet = Wallet::from_signing_key(key.private_key().clone());
+        wallet.chain_id = self.chain_id;
+        wallet
+    }
+
+    pub fn next_address(&mut self) -> Wallet {
+        let wallet = self.derive(self.address_count);
+        self.address_count += 1;
+        wallet
+    }
+
+    /// Every address handed out so far.
+    pub fn wallets(&self) -> Vec<Wallet> {
+        (0..self.address_count).map(|index| self.derive(index)).collect()
+    }
+
+    /// Every address is credited the initial balance, which is only counted for the addresses that appear on
+    /// the chain, so handing out addresses does not make the wallet richer.
+    pub fn calculate_balance(&self, blockchain: &Blockchain) -> u64 {
+        self.wallets().iter()
+            .filter(|wallet| is_used(&wallet.public_key, blockchain))
+            .map(|wallet| wallet.calculate_balance(blockchain))
+            .sum()
+    }
+
+    /// Balance over every address handed out so far, with funds needing `confirmations` confirmations to be spent.
+    pub fn balance_report(&self, blockchain: &Blockchain, transaction_pool: &TransactionPool, confirmations: usize) -> BalanceReport {
+        let public_keys = self.wallets().into_iter().map(|wallet| wallet.public_key).collect::<Vec<_>>();
+        let immature_incoming = public_keys.iter()
+            .map(|public_key| Wallet::immature_incoming(public_key, blockchain, confirmations))
+            .fold(0u64, u64::saturating_add);
+        let (pending_incoming, pending_outgoing) = transaction_pool.pending_amounts(|address| public_keys.iter().any(|public_key| public_key == address));
+        BalanceReport::new(self.calculate_balance(blockchain), immature_incoming, pending_incoming, pending_outgoing)
+    }
+}
+
+/// Whether the address appears in any transaction on the chain. Only such addresses are credited the initial balance
+/// when summing over several addresses.
+pub(crate) fn is_used(address: &str, blockchain: &Blockchain) -> bool {
+    blockchain.chain.iter().flat_map(|block| block.data.iter()).any(|transaction| transaction.involves(address))
+}
+
+impl fmt::Display for HdWalletError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            HdWalletError::InvalidMnemonic => write!(f, "not a valid english BIP-39 mnemonic"),
+            HdWalletError::InvalidSeed => write!(f, "not a valid HD wallet seed"),
+        }
+    }
+}
+
+impl Error for HdWalletError {}
@@ -0,0 +1,201 @@
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+use aes_gcm::aead::{Aead, Payload};
+use aes_gcm::{Aes256Gcm, Key, KeyInit, Nonce};
+use argon2::{Algorithm, Argon2, Params, Version};
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use crate::wallet::hd_wallet::HdWallet;
+use crate::wallet::wallet::Wallet;
+
+pub const KEYSTORE_VERSION: u8 = 1;
+const SALT_LEN: usize = 16;
+const NONCE_LEN: usize = 12;
+
+/// A wallet's secret key sealed with a password, as stored on disk. The encryption key is derived from the
+/// password with Argon2id and the secret key is encrypted with AES-256-GCM, bound to the wallet address,
+/// so a wrong password, a tampered file or a swapped address all fail to decrypt.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Keystore {
+    pub version: u8,
+    #[serde(default)]
+    pub kind: KeystoreKind,
+    /// For an HD wallet, its first address.
+    pub address: String,
+    /// For an HD wallet, how many addresses it handed out. Not secret, and only ever hides or shows addresses.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub address_count: Option<u32>,
+    pub kdf: KdfParams,
+    pub nonce: String,
+    pub ciphertext: String,
+}
+
+/// What the ciphertext holds. Keystores written before HD wallets existed hold a single key.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum KeystoreKind {
+    #[default]
+    Key,
+    /// The seed of an `HdWallet`, from which every address is derived.
+    HdSeed,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct KdfParams {
+    pub salt: String,
+    pub memory_kib: u32,
+    pub iterations: u32,
+    pub parallelism: u32,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+#[serde(tag = "code", rename_all = "snake_case")]
+pub enum KeystoreError {
+    Io { message: String },
+    Malformed { message: String },
+    UnsupportedVersion { version: u8 },
+    WrongPassword,
+    InvalidKey,
+    NotHdWallet,
+}
+
+impl Keystore {
+    pub fn encrypt(wallet: &Wallet, password: &str) -> Keystore {
+        Keystore::seal(KeystoreKind::Key, &wallet.secret_key(), &wallet.public_key, password)
+    }
+
+    /// Seals the seed of an HD wallet rather than a single key, so every derived address can be restored from it.
+    pub fn encrypt_hd(hd_wallet: &HdWallet, password: &str) -> Keystore {
+        let mut keystore = Keystore::seal(KeystoreKind::HdSeed, &hd_wallet.seed(), &hd_wallet.derive(0).public_key, password);
+        keystore.address_count = Some(hd_wallet.address_count);
+        keystore
+    }
+
+    fn seal(kind: KeystoreKind, secret: &[u8], address: &str, password: &str) -> Keystore {
+        let mut salt = [0u8; SALT_LEN];
+        let mut nonce = [0u8; NONCE_LEN];
+        rand::thread_rng().fill_bytes(&mut salt);
+        rand::thread_rng().fill_bytes(&mut nonce);
+        let kdf = KdfParams {
+            salt: hex::encode(salt),
+            memory_kib: Params::DEFAULT_M_COST,
+            iterations: Params::DEFAULT_T_COST,
+            parallelism: Params::DEFAULT_P_COST,
+        };
+        let cipher = Aes256Gcm::new(&kdf.derive_key(password).unwrap());
+        let payload = Payload { msg: secret, aad: address.as_bytes() };
+        let ciphertext = cipher.encrypt(Nonce::from_slice(&nonce), payload).unwrap();
+        Keystore {
+            version: KEYSTORE_VERSION,
+            kind,
+            address: address.to_string(),
+            address_count: None,
+            kdf,
+            nonce: hex::encode(nonce),
+            ciphertext: hex::encode(ciphertext),
+        }
+    }
+
+    /// The wallet of a single key keystore, or the first address of an HD wallet.
+    pub fn decrypt(&self, password: &str) -> Result<Wallet, KeystoreError> {
+        match self.kind {
+            KeystoreKind::Key => Wallet::from_secret_key(&self.open(password)?),
+            KeystoreKind::HdSeed => Ok(self.decrypt_hd(password)?.derive(0)),
+        }
+    }
+
+    pub fn decrypt_hd(&self, password: &str) -> Result<HdWallet, KeystoreError> {
+        if self.kind != KeystoreKind::HdSeed {
+            return Err(KeystoreError::NotHdWallet);
+        }
+        let mut hd_wallet = HdWallet::from_seed(&self.open(password)?).map_err(|e| KeystoreError::malformed(&e.to_string()))?;
+        hd_wallet.address_count = self.address_count.unwrap_or_default();
+        Ok(hd_wallet)
+    }
+
+    fn open(&self, password: &str) -> Result<Vec<u8>, KeystoreError> {
+        if self.version != KEYSTORE_VERSION {
+            return Err(KeystoreError::UnsupportedVersion { version: self.version });
+        }
+        let nonce = hex::decode(&self.nonce).ok().filter(|nonce| nonce.len() == NONCE_LEN)
+            .ok_or_else(|| KeystoreError::malformed("nonce is not 12 hex encoded bytes"))?;
+        let ciphertext = hex::decode(&self.ciphertext).map_err(|_| KeystoreError::malformed("ciphertext is not hex"))?;
+        let cipher = Aes256Gcm::new(&self.kdf.derive_key(password)?);
+        // the address is authenticated data, so this also fails if it was edited
+        let payload = Payload { msg: &ciphertext, aad: self.address.as_bytes() };
+        cipher.decrypt(Nonce::from_slice(&nonce), payload).map_err(|_| KeystoreError::WrongPassword)
+    }
+
+    /// Opens the wallet in the keystore at `path`, or creates a new wallet there on first run.
+    pub fn load_or_create(path: &Path, password: &str) -> Result<Wallet, KeystoreError> {
+        if path.exists() {
+            return Keystore::load(path)?.decrypt(password);
+        }
+        let wallet = Wallet::new();
+        Keystore::encrypt(&wallet, password).save(path)?;
+        println!("Generated new wallet {} in {}", wallet.public_key, path.display());
+        Ok(wallet)
+    }
+
+    pub fn load(path: &Path) -> Result<Keystore, KeystoreError> {
+        let json = fs::read_to_string(path).map_err(|e| KeystoreError::io(path, e))?;
+        serde_json::from_str(&json).map_err(|e| KeystoreError::malformed(&e.to_string()))
+    }
+
+    /// Writes the keystore readable by the owner only, without overwriting an existing one.
+    pub fn save(&self, path: &Path) -> Result<(), KeystoreError> {
+        if path.exists() {
+            return Err(KeystoreError::Io { message: format!("{} already exists", path.display()) });
+        }
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).map_err(|e| KeystoreError::io(parent, e))?;
+        }
+        fs::write(path, serde_json::to_string_pretty(self).unwrap()).map_err(|e| KeystoreError::io(path, e))?;
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            fs::set_permissions(path, fs::Permissions::from_mode(0o600)).map_err(|e| KeystoreError::io(path, e))?;
+        }
+        Ok(())
+    }
+}
+
+impl KdfParams {
+    fn derive_key(&self, password: &str) -> Result<Key<Aes256Gcm>, KeystoreError> {
+        let salt = hex::decode(&self.salt).map_err(|_| KeystoreError::malformed("salt is not hex"))?;
+        let params = Params::new(self.memory_kib, self.iterations, self.parallelism, Some(32))
+            .map_err(|e| KeystoreError::malformed(&e.to_string()))?;
+        let mut key = Key::<Aes256Gcm>::default();
+        Argon2::new(Algorithm::Argon2id, Version::V0x13, params)
+            .hash_password_into(password.as_bytes(), &salt, key.as_mut_slice())
+            .map_err(|e| KeystoreError::malformed(&e.to_string()))?;
+        Ok(key)
+    }
+}
+
+impl KeystoreError {
+    fn io(path: &Path, e: std::io::Error) -> KeystoreError {
+        KeystoreError::Io { message: format!("{}: {e}", path.display()) }
+    }
+
+    fn malformed(message: &str) -> KeystoreError {
+        KeystoreError::Malformed { message: message.to_string() }
+    }
+}
+
+impl fmt::Display for KeystoreError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            KeystoreError::Io { message } => write!(f, "cannot access keystore {message}"),
+            KeystoreError::Malformed { message } => write!(f, "keystore is malformed: {message}"),
+            KeystoreError::UnsupportedVersion { version } => write!(f, "keystore version {version} is unknown"),
+            KeystoreError::WrongPassword => write!(f, "wrong password or corrupted keystore"),
+            KeystoreError::InvalidKey => write!(f, "not a secp256k1 secret key"),
+            KeystoreError::NotHdWallet => write!(f, "keystore holds a single key, not an HD wallet"),
+        }
+    }
+}
+
+impl Error for KeystoreError {}
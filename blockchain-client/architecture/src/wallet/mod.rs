@@ -1,3 +1,8 @@
 pub mod transaction;
+#[allow(clippy::module_inception)]
 pub mod wallet;
 pub mod transaction_pool;
+pub mod keystore;
+pub mod hd_wallet;
+pub mod address;
+pub mod watch_only;
@@ -1,28 +1,66 @@
+use std::error::Error;
 use std::fmt;
+use std::str::FromStr;
 use chrono::{DateTime, Local, Utc};
 use ecdsa::VerifyingKey;
-use serde::{Deserialize, Serialize};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
 use serde_json::json;
-use uuid::Uuid;
-use crate::utils::config::MINING_REWARD;
-use crate::wallet::wallet::Wallet;
+use sha2::{Digest, Sha256};
+use crate::blockchain::blockchain::Blockchain;
+use crate::utils::config::{MINING_REWARD, REPLACEMENT_FEE_INCREMENT};
+use crate::wallet::transaction_pool::{PoolError, TransactionPool};
+use crate::wallet::wallet::{Wallet, BLOCKCHAIN_WALLET_ADDRESS};
 use crate::utils::utils::crypto_hash;
 
+/// Version 1 only signed the outputs, chain id and nonce. Version 2 signs every field of the transaction.
+pub const LEGACY_TRANSACTION_VERSION: u16 = 1;
+pub const TRANSACTION_VERSION: u16 = 2;
+
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub struct Transaction {
     pub id: TransactionId,
+    // transactions saved before versioning carry no version and are legacy ones
+    #[serde(default = "legacy_version")]
+    pub version: u16,
     pub input: Option<TransactionInput>,
     pub outputs: Vec<TransactionOutput>,
 }
 
-#[derive(Eq, PartialEq, Serialize, Deserialize, Debug, Clone, Copy, Hash, Default)]
-pub struct TransactionId(pub Uuid);
+fn legacy_version() -> u16 {
+    LEGACY_TRANSACTION_VERSION
+}
+
+/// SHA-256 of the signed transaction contents, serialized as hex. Changing anything in a transaction,
+/// including its signature, changes its id.
+#[derive(Eq, PartialEq, Ord, PartialOrd, Debug, Clone, Copy, Hash, Default)]
+pub struct TransactionId(pub [u8; 32]);
+
+/// Why a transaction cannot be built or does not verify.
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+#[serde(tag = "code", rename_all = "snake_case")]
+pub enum TxError {
+    MissingInput,
+    UnsupportedVersion { version: u16 },
+    InvalidAddress,
+    InvalidSignatureEncoding,
+    SignatureMismatch,
+    AmountMismatch { input: u64, outputs: u64 },
+    InsufficientFunds { balance: u64, amount: u64 },
+    ZeroAmount,
+    SelfPayment,
+    Overflow,
+    MissingSenderOutput,
+    FeeTooLow { current: u64, fee: u64 },
+    WrongSigner,
+}
 
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub struct TransactionInput {
     pub timestamp: DateTime<Utc>,
     pub amount: u64,
     pub address: String,
+    pub chain_id: u32,
+    pub nonce: u64,
     pub signature: String,
 }
 
@@ -43,55 +81,255 @@ impl TransactionOutput {
     }
 }
 
-impl Transaction {
-    pub fn new(sender_wallet: &Wallet, recipient: String, amount: u64) -> Transaction {
-        if amount > sender_wallet.balance {
-            return Transaction {
-                id: TransactionId(Uuid::new_v4()),
-                input: None,
-                outputs: vec![],
-            };
+/// Everything of a transaction but the signature. A node can build one for any address, and whoever holds
+/// the key, e.g. on an air-gapped machine, signs it without needing the chain.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct UnsignedTransaction {
+    pub version: u16,
+    pub timestamp: DateTime<Utc>,
+    /// The sender's balance the transaction spends, whatever the outputs leave of it is the fee.
+    pub amount: u64,
+    pub address: String,
+    pub chain_id: u32,
+    pub nonce: u64,
+    pub outputs: Vec<TransactionOutput>,
+}
+
+fn check_payment(sender: &str, recipient: &str, amount: u64) -> Result<(), TxError> {
+    if amount == 0 {
+        return Err(TxError::ZeroAmount);
+    }
+    if recipient == sender {
+        return Err(TxError::SelfPayment);
+    }
+    Ok(())
+}
+
+impl UnsignedTransaction {
+    /// Builds the next transaction of `address`: a replacement outbidding its pending transaction if it has one,
+    /// otherwise a new payment out of its balance on the chain.
+    pub fn build(address: &str, chain_id: u32, recipient: String, amount: u64, transaction_pool: &TransactionPool, blockchain: &Blockchain) -> Result<UnsignedTransaction, PoolError> {
+        let balance = Wallet::address_balance(address, blockchain);
+        if amount > balance {
+            return Err(PoolError::InsufficientBalance { balance, amount });
         }
-        let sender_transaction_output = TransactionOutput::new(sender_wallet.balance - amount, sender_wallet.public_key.clone());
-        let reciever_transaction_output = TransactionOutput::new(amount, recipient);
-        let outputs = vec![sender_transaction_output, reciever_transaction_output];
+        let unsigned = match transaction_pool.existing_transaction(address) {
+            // the pending transaction is replaced rather than mutated, so the replacement has to outbid it
+            Some(pending) => UnsignedTransaction::replacing(&pending, recipient, amount, pending.fee() + REPLACEMENT_FEE_INCREMENT),
+            None => UnsignedTransaction::payment(address, balance, chain_id, blockchain.last_nonce(address) + 1, recipient, amount),
+        };
+        unsigned.map_err(|reason| PoolError::Invalid { reason })
+    }
 
-        Transaction::transaction_with_outputs(sender_wallet, outputs)
+    /// Pays `amount` out of `balance` to the recipient and returns the rest to the sender.
+    pub fn payment(address: &str, balance: u64, chain_id: u32, nonce: u64, recipient: String, amount: u64) -> Result<UnsignedTransaction, TxError> {
+        check_payment(address, &recipient, amount)?;
+        if amount > balance {
+            return Err(TxError::InsufficientFunds { balance, amount });
+        }
+        Ok(UnsignedTransaction {
+            version: TRANSACTION_VERSION,
+            timestamp: Local::now().with_timezone(&Utc),
+            amount: balance,
+            address: address.to_string(),
+            chain_id,
+            nonce,
+            outputs: vec![TransactionOutput::new(balance - amount, address.to_string()), TransactionOutput::new(amount, recipient)],
+        })
+    }
+
+    /// A replacement of the pending transaction, which gets a new id as its contents change, paying `amount` to the recipient
+    /// and leaving `fee` unspent. The pool only accepts it if the fee is higher than the current one.
+    pub fn replacing(pending: &Transaction, recipient: String, amount: u64, fee: u64) -> Result<UnsignedTransaction, TxError> {
+        let input = pending.input.as_ref().ok_or(TxError::MissingInput)?;
+        check_payment(&input.address, &recipient, amount)?;
+        let additional_fee = fee.checked_sub(pending.fee()).ok_or(TxError::FeeTooLow { current: pending.fee(), fee })?;
+        let mut outputs = pending.outputs.clone();
+        let sender_output = outputs.iter_mut().find(|output| output.address == input.address)
+            .ok_or(TxError::MissingSenderOutput)?;
+        let spent = amount.checked_add(additional_fee).ok_or(TxError::Overflow)?;
+        sender_output.amount = sender_output.amount.checked_sub(spent)
+            .ok_or(TxError::InsufficientFunds { balance: sender_output.amount, amount: spent })?;
+        outputs.push(TransactionOutput::new(amount, recipient));
+        // a replacement spends the same input, so it keeps the amount and nonce
+        Ok(UnsignedTransaction {
+            version: TRANSACTION_VERSION,
+            timestamp: Local::now().with_timezone(&Utc),
+            amount: input.amount,
+            address: input.address.clone(),
+            chain_id: input.chain_id,
+            nonce: input.nonce,
+            outputs,
+        })
+    }
+
+    /// Signs with `sender_wallet`, which has to hold the key of `address`.
+    pub fn sign(self, sender_wallet: &Wallet) -> Result<Transaction, TxError> {
+        if sender_wallet.public_key != self.address {
+            return Err(TxError::WrongSigner);
+        }
+        let mut transaction = self.into_transaction(String::new());
+        let sighash = transaction.sighash().ok_or(TxError::UnsupportedVersion { version: transaction.version })?;
+        transaction.input.as_mut().unwrap().signature = sender_wallet.sign(&sighash);
+        transaction.id = transaction.compute_id();
+        Ok(transaction)
+    }
+
+    pub fn fee(&self) -> u64 {
+        let outputs = self.outputs.iter().try_fold(0u64, |total, output| total.checked_add(output.amount));
+        outputs.map_or(0, |outputs| self.amount.saturating_sub(outputs))
+    }
 
+    fn into_transaction(self, signature: String) -> Transaction {
+        Transaction {
+            id: TransactionId::default(),
+            version: self.version,
+            input: Some(TransactionInput {
+                timestamp: self.timestamp,
+                amount: self.amount,
+                address: self.address,
+                chain_id: self.chain_id,
+                nonce: self.nonce,
+                signature,
+            }),
+            outputs: self.outputs,
+        }
+    }
+}
+
+impl Transaction {
+    pub fn new(sender_wallet: &Wallet, recipient: String, amount: u64) -> Result<Transaction, TxError> {
+        UnsignedTransaction::payment(&sender_wallet.public_key, sender_wallet.balance, sender_wallet.chain_id,
+                                     sender_wallet.nonce, recipient, amount)?
+            .sign(sender_wallet)
     }
 
     pub fn sign(&mut self, sender_wallet: &Wallet) {
-        let input = TransactionInput {
+        self.sign_with_nonce(sender_wallet, sender_wallet.nonce);
+    }
+
+    /// Signs with the current transaction version, upgrading legacy transactions that get re-signed.
+    fn sign_with_nonce(&mut self, sender_wallet: &Wallet, nonce: u64) {
+        self.version = TRANSACTION_VERSION;
+        self.input = Some(TransactionInput {
             timestamp: Local::now().with_timezone(&Utc),
             amount: sender_wallet.balance,
             address: sender_wallet.public_key.clone(),
-            signature: sender_wallet.sign(crypto_hash(&[json!(&self.outputs)]).as_str()),
+            chain_id: sender_wallet.chain_id,
+            nonce,
+            signature: String::new(),
+        });
+        let sighash = self.sighash().unwrap();
+        self.input.as_mut().unwrap().signature = sender_wallet.sign(&sighash);
+        self.id = self.compute_id();
+    }
+
+    /// The digest the sender signs, `None` without an input or for an unknown version.
+    pub fn sighash(&self) -> Option<String> {
+        let input = self.input.as_ref()?;
+        match self.version {
+            LEGACY_TRANSACTION_VERSION => Some(crypto_hash(&[json!({ "outputs": &self.outputs, "chain_id": input.chain_id, "nonce": input.nonce })])),
+            // commits to everything but the signature itself: the input amount, timestamp and address can no
+            // longer be altered in transit, and the chain id and nonce keep the transaction from being replayed
+            TRANSACTION_VERSION => Some(crypto_hash(&[json!({
+                "version": self.version,
+                "timestamp": input.timestamp,
+                "amount": input.amount,
+                "address": &input.address,
+                "chain_id": input.chain_id,
+                "nonce": input.nonce,
+                "outputs": &self.outputs,
+            })])),
+            _ => None,
+        }
+    }
+
+    /// Hashes the canonical JSON of the version, input and outputs. `json!` keeps object keys sorted,
+    /// so the encoding does not depend on field order.
+    pub fn compute_id(&self) -> TransactionId {
+        let contents = match self.version {
+            // legacy ids were computed before transactions had a version
+            LEGACY_TRANSACTION_VERSION => json!({ "input": &self.input, "outputs": &self.outputs }),
+            _ => json!({ "version": self.version, "input": &self.input, "outputs": &self.outputs }),
         };
-        self.input = Some(input);
+        TransactionId(Sha256::digest(contents.to_string().as_bytes()).into())
+    }
+
+    pub fn has_valid_id(&self) -> bool {
+        self.id == self.compute_id()
+    }
+
+    /// Checks the signature and that the outputs do not spend more than the input. Never panics,
+    /// whatever a peer sent.
+    pub fn verify(&self) -> Result<(), TxError> {
+        let input = self.input.as_ref().ok_or(TxError::MissingInput)?;
+        let hash = self.sighash().ok_or(TxError::UnsupportedVersion { version: self.version })?;
+        let address = hex::decode(&input.address).map_err(|_| TxError::InvalidAddress)?;
+        let verifying_key = VerifyingKey::from_sec1_bytes(&address).map_err(|_| TxError::InvalidAddress)?;
+        Wallet::verify(verifying_key, hash.as_str(), &input.signature)?;
+        // whatever the outputs leave of the input is the fee
+        let outputs = self.output_total().unwrap_or(u64::MAX);
+        if outputs > input.amount {
+            return Err(TxError::AmountMismatch { input: input.amount, outputs });
+        }
+        Ok(())
     }
 
-    pub fn verify(&self) -> bool {
-        let input = self.input.as_ref().unwrap();
-        let hash = crypto_hash(&[json!(&self.outputs)]);
-        let verifying_key = VerifyingKey::from_sec1_bytes(&hex::decode(&input.address).unwrap()).unwrap();
-        Wallet::verify(verifying_key, hash.as_str(), &input.signature)
+    /// Sum of the outputs, `None` if it overflows.
+    pub fn output_total(&self) -> Option<u64> {
+        self.outputs.iter().try_fold(0u64, |total, output| total.checked_add(output.amount))
     }
 
-    pub fn update(&mut self, sender_wallet: &Wallet, recipient: String, amount: u64) -> Result<Transaction, &'static str> {
-        let sender_output = self.outputs.iter_mut().find(|output| output.address == sender_wallet.public_key);
-        if (amount > sender_wallet.balance) || sender_output.is_none() {
-            return Err("Amount exceeds balance");
+    /// What the input leaves unspent, collected by the miner of the block that includes the transaction.
+    pub fn fee(&self) -> u64 {
+        match (&self.input, self.output_total()) {
+            (Some(input), Some(outputs)) => input.amount.saturating_sub(outputs),
+            _ => 0,
         }
-        let sender_output = sender_output.unwrap();
-        sender_output.amount = sender_output.amount - amount;
+    }
+
+    pub fn chain_id(&self) -> Option<u32> {
+        self.input.as_ref().map(|input| input.chain_id)
+    }
+
+    pub fn nonce(&self) -> Option<u64> {
+        self.input.as_ref().map(|input| input.nonce)
+    }
+
+    /// Whether `address` spends or receives anything in this transaction.
+    pub fn involves(&self, address: &str) -> bool {
+        self.input.as_ref().is_some_and(|input| input.address == address)
+            || self.outputs.iter().any(|output| output.address == address)
+    }
+
+    /// Rewards are signed by the blockchain wallet and are exempt from the nonce rules.
+    pub fn is_reward(&self) -> bool {
+        self.input.as_ref().is_some_and(|input| input.address == BLOCKCHAIN_WALLET_ADDRESS)
+    }
+
+    /// Adds a payment to this transaction out of the sender's change. Nothing is changed on error.
+    pub fn update(&mut self, sender_wallet: &Wallet, recipient: String, amount: u64) -> Result<Transaction, TxError> {
+        check_payment(&sender_wallet.public_key, &recipient, amount)?;
+        let sender_output = self.outputs.iter_mut().find(|output| output.address == sender_wallet.public_key)
+            .ok_or(TxError::MissingSenderOutput)?;
+        sender_output.amount = sender_output.amount.checked_sub(amount)
+            .ok_or(TxError::InsufficientFunds { balance: sender_output.amount, amount })?;
         self.outputs.push(TransactionOutput::new(amount, recipient));
-        Transaction::sign(self, sender_wallet);
+        let nonce = self.nonce().unwrap_or(sender_wallet.nonce);
+        self.sign_with_nonce(sender_wallet, nonce);
         Ok(self.to_owned())
     }
 
+    /// Builds a replacement of this pending transaction, which gets a new id as its contents change, paying `amount` to the recipient
+    /// and leaving `fee` unspent. The pool only accepts it if the fee is higher than the current one.
+    pub fn replace(&self, sender_wallet: &Wallet, recipient: String, amount: u64, fee: u64) -> Result<Transaction, TxError> {
+        UnsignedTransaction::replacing(self, recipient, amount, fee)?.sign(sender_wallet)
+    }
+
     pub fn transaction_with_outputs(sender_wallet: &Wallet, outputs: Vec<TransactionOutput>) -> Transaction {
         let mut transaction = Transaction {
-            id: TransactionId(Uuid::new_v4()),
+            id: TransactionId::default(),
+            version: TRANSACTION_VERSION,
             input: None,
             outputs,
         };
@@ -100,7 +338,18 @@ impl Transaction {
     }
 
     pub fn reward_transaction(miner_wallet: &Wallet, blockchain_wallet: &Wallet) -> Transaction {
-        Transaction::transaction_with_outputs(blockchain_wallet, vec![TransactionOutput::new(MINING_REWARD, miner_wallet.public_key.clone())])
+        Transaction::reward_transaction_with_fees(miner_wallet, blockchain_wallet, 0)
+    }
+
+    pub fn reward_transaction_with_fees(miner_wallet: &Wallet, blockchain_wallet: &Wallet, fees: u64) -> Transaction {
+        let mut transaction = Transaction {
+            id: TransactionId::default(),
+            version: TRANSACTION_VERSION,
+            input: None,
+            outputs: vec![TransactionOutput::new(MINING_REWARD.saturating_add(fees), miner_wallet.public_key.clone())],
+        };
+        transaction.sign_with_nonce(blockchain_wallet, 0);
+        transaction
     }
 
 }
@@ -108,19 +357,40 @@ impl Transaction {
 
 impl fmt::Display for Transaction {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "Transaction: \n\tid: {}\n\tinput: {:?}\n\toutputs: {:?}\n", self.id.0, self.input, self.outputs)
+        write!(f, "Transaction: \n\tid: {}\n\tversion: {}\n\tinput: {:?}\n\toutputs: {:?}\n", self.id, self.version, self.input, self.outputs)
     }
 }
 
 impl fmt::Display for TransactionId {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "{}", self.0)
+        write!(f, "{}", hex::encode(self.0))
+    }
+}
+
+impl FromStr for TransactionId {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let bytes = hex::decode(s).map_err(|_| "Transaction id is not hex")?;
+        bytes.try_into().map(TransactionId).map_err(|_| "Transaction id is not 32 bytes long")
+    }
+}
+
+impl Serialize for TransactionId {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error> where S: Serializer {
+        serializer.serialize_str(&self.to_string())
+    }
+}
+
+impl<'de> Deserialize<'de> for TransactionId {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error> where D: Deserializer<'de> {
+        String::deserialize(deserializer)?.parse().map_err(serde::de::Error::custom)
     }
 }
 
 impl fmt::Display for TransactionInput {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "TransactionInput: \n\ttimestamp: {}\n\tamount: {}\n\taddress: {}\n\tsignature: {}\n", self.timestamp, self.amount, self.address, self.signature)
+        write!(f, "TransactionInput: \n\ttimestamp: {}\n\tamount: {}\n\taddress: {}\n\tchain_id: {}\n\tnonce: {}\n\tsignature: {}\n", self.timestamp, self.amount, self.address, self.chain_id, self.nonce, self.signature)
     }
 }
 
@@ -130,5 +400,24 @@ impl fmt::Display for TransactionOutput {
     }
 }
 
+impl fmt::Display for TxError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TxError::MissingInput => write!(f, "transaction has no signed input"),
+            TxError::UnsupportedVersion { version } => write!(f, "transaction version {version} is unknown"),
+            TxError::InvalidAddress => write!(f, "input address is not a hex encoded SEC1 public key"),
+            TxError::InvalidSignatureEncoding => write!(f, "signature is not a hex encoded DER signature"),
+            TxError::SignatureMismatch => write!(f, "signature does not match the transaction"),
+            TxError::AmountMismatch { input, outputs } => write!(f, "outputs total {outputs} but the input is only {input}"),
+            TxError::InsufficientFunds { balance, amount } => write!(f, "amount {amount} exceeds the balance of {balance}"),
+            TxError::ZeroAmount => write!(f, "amount must be greater than zero"),
+            TxError::SelfPayment => write!(f, "sender and recipient are the same address"),
+            TxError::Overflow => write!(f, "outputs total more than a u64 can hold"),
+            TxError::MissingSenderOutput => write!(f, "transaction has no output for the sender"),
+            TxError::FeeTooLow { current, fee } => write!(f, "fee {fee} is lower than the current fee of {current}"),
+            TxError::WrongSigner => write!(f, "the signing key does not belong to the sender"),
+        }
+    }
+}
 
-
+impl Error for TxError {}
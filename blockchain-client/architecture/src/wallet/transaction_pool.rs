@@ -1,42 +1,358 @@
-use crate::wallet::transaction::Transaction;
+use std::cmp::Ordering;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::error::Error;
+use std::fmt;
+use chrono::{DateTime, TimeDelta, Utc};
+use serde::Serialize;
+use crate::blockchain::block::Block;
+use crate::blockchain::blockchain::Blockchain;
+use crate::utils::config::{MEMPOOL_EXPIRY_SECS, MEMPOOL_MAX_BYTES, MEMPOOL_MAX_TRANSACTIONS};
+use crate::wallet::address::Address;
+use crate::wallet::transaction::{Transaction, TransactionId, TxError, TRANSACTION_VERSION};
+use crate::wallet::wallet::Wallet;
+
+/// Bounds of the pool. Once a limit is reached the entries paying the lowest fee rate are evicted first.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PoolLimits {
+    pub max_transactions: usize,
+    pub max_bytes: usize,
+    pub expiry: TimeDelta,
+}
+
+impl Default for PoolLimits {
+    fn default() -> Self {
+        PoolLimits {
+            max_transactions: MEMPOOL_MAX_TRANSACTIONS,
+            max_bytes: MEMPOOL_MAX_BYTES,
+            expiry: TimeDelta::seconds(MEMPOOL_EXPIRY_SECS),
+        }
+    }
+}
+
+struct PoolEntry {
+    transaction: Transaction,
+    size: usize,
+    added_at: DateTime<Utc>,
+}
+
+impl PoolEntry {
+    /// Orders entries by fee per byte, comparing cross products so that no precision is lost.
+    fn cmp_fee_rate(&self, fee: u64, size: usize) -> Ordering {
+        (self.transaction.fee() as u128 * size as u128).cmp(&(fee as u128 * self.size as u128))
+    }
+}
 
 pub struct TransactionPool {
-    pub transactions: Vec<Transaction>,
+    entries: HashMap<TransactionId, PoolEntry>,
+    by_sender: HashMap<String, TransactionId>,
+    // replaced id -> replacing id, the oldest records are dropped once there are as many as pool entries
+    replaced: HashMap<TransactionId, TransactionId>,
+    replaced_order: VecDeque<TransactionId>,
+    total_bytes: usize,
+    limits: PoolLimits,
 }
 
+/// Why a transaction was not admitted to the pool.
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+#[serde(tag = "code", rename_all = "snake_case")]
+pub enum PoolError {
+    /// The transaction could not even be decoded.
+    Malformed { message: String },
+    Invalid { reason: TxError },
+    UnsupportedVersion { version: u16, minimum: u16 },
+    IdMismatch { expected: TransactionId },
+    WrongChain { expected: u32, found: u32 },
+    InvalidRecipient { address: String },
+    InsufficientBalance { balance: u64, amount: u64 },
+    StaleNonce { last: u64, nonce: u64 },
+    ReplacementInputMismatch { pending: TransactionId },
+    ReplacementFeeTooLow { pending: TransactionId, fee: u64, minimum: u64 },
+    AlreadyReplaced { by: TransactionId },
+    PoolFull,
+}
+
+impl PoolError {
+    /// Whether the transaction could never have been valid, as opposed to losing out against the pool's
+    /// current state, which honest peers run into when messages arrive out of order.
+    pub fn is_invalid(&self) -> bool {
+        matches!(self, PoolError::Malformed { .. } | PoolError::Invalid { .. } | PoolError::IdMismatch { .. } | PoolError::WrongChain { .. } | PoolError::InvalidRecipient { .. })
+    }
+}
+
+impl Default for TransactionPool {
+    fn default() -> Self {
+        Self::new()
+    }
+}
 
 impl TransactionPool {
     pub fn new() -> TransactionPool {
+        TransactionPool::with_limits(PoolLimits::default())
+    }
+
+    pub fn with_limits(limits: PoolLimits) -> TransactionPool {
         TransactionPool {
-            transactions: vec![],
+            entries: HashMap::new(),
+            by_sender: HashMap::new(),
+            replaced: HashMap::new(),
+            replaced_order: VecDeque::new(),
+            total_bytes: 0,
+            limits,
         }
     }
 
-    pub fn update_or_add_transaction(&mut self, transaction: Transaction) {
-        let transaction_id = transaction.id;
-        let existing_transaction = self.transactions.iter_mut().find(|t| t.id == transaction_id);
-        match existing_transaction {
-            Some(existing_transaction) => *existing_transaction = transaction,
-            None => self.transactions.push(transaction),
+    /// Pending transactions, oldest first.
+    pub fn transactions(&self) -> Vec<Transaction> {
+        let mut entries = self.entries.values().collect::<Vec<_>>();
+        entries.sort_by_key(|entry| (entry.added_at, entry.transaction.id));
+        entries.into_iter().map(|entry| entry.transaction.clone()).collect()
+    }
+
+    pub fn get(&self, id: &TransactionId) -> Option<&Transaction> {
+        self.entries.get(id).map(|entry| &entry.transaction)
+    }
+
+    /// When the transaction was admitted to the pool.
+    pub fn added_at(&self, id: &TransactionId) -> Option<DateTime<Utc>> {
+        self.entries.get(id).map(|entry| entry.added_at)
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn size_in_bytes(&self) -> usize {
+        self.total_bytes
+    }
+
+    /// Validates the transaction against the chain state and the pending transactions, and stores it
+    /// only if it is valid. A sender's pending transaction is only replaced by one that spends the same input
+    /// and pays a strictly higher fee. Receiving a transaction that is already pending is a no-op.
+    pub fn update_or_add_transaction(&mut self, transaction: Transaction, blockchain: &Blockchain) -> Result<(), PoolError> {
+        self.add_transaction_at(transaction, blockchain, Utc::now())
+    }
+
+    /// Admits a transaction that was already pending before, keeping the time it was first admitted so that
+    /// it does not outlive the expiry by being saved and restored.
+    pub fn restore_transaction(&mut self, transaction: Transaction, blockchain: &Blockchain, added_at: DateTime<Utc>) -> Result<(), PoolError> {
+        self.add_transaction_at(transaction, blockchain, added_at)
+    }
+
+    fn add_transaction_at(&mut self, transaction: Transaction, blockchain: &Blockchain, added_at: DateTime<Utc>) -> Result<(), PoolError> {
+        if self.get(&transaction.id) == Some(&transaction) {
+            return Ok(());
+        }
+        if let Some(by) = self.replaced_by(&transaction.id) {
+            return Err(PoolError::AlreadyReplaced { by });
+        }
+        let replaces = self.validate(&transaction, blockchain)?;
+        let size = serde_json::to_vec(&transaction).map(|bytes| bytes.len()).unwrap_or(usize::MAX);
+        self.make_room(&transaction, size, replaces)?;
+
+        if let Some(replaced) = replaces {
+            println!("Transaction {} replaces {replaced}", transaction.id);
+            self.remove(&replaced);
+            self.record_replacement(replaced, transaction.id);
         }
+        let address = transaction.input.as_ref().unwrap().address.clone();
+        self.by_sender.insert(address, transaction.id);
+        self.total_bytes += size;
+        self.entries.insert(transaction.id, PoolEntry { transaction, size, added_at });
+        Ok(())
+    }
+
+    /// The transaction that replaced the given one while it was pending.
+    pub fn replaced_by(&self, id: &TransactionId) -> Option<TransactionId> {
+        self.replaced.get(id).copied()
+    }
+
+    fn record_replacement(&mut self, replaced: TransactionId, replacement: TransactionId) {
+        self.replaced.insert(replaced, replacement);
+        self.replaced_order.push_back(replaced);
+        while self.replaced_order.len() > self.limits.max_transactions {
+            if let Some(oldest) = self.replaced_order.pop_front() {
+                self.replaced.remove(&oldest);
+            }
+        }
+    }
+
+    /// Returns the id of the pending transaction that the new one replaces, if any.
+    fn validate(&self, transaction: &Transaction, blockchain: &Blockchain) -> Result<Option<TransactionId>, PoolError> {
+        let input = transaction.input.as_ref().ok_or(PoolError::Invalid { reason: TxError::MissingInput })?;
+        // legacy transactions do not sign every field, chains holding them are not valid either
+        if transaction.version < TRANSACTION_VERSION {
+            return Err(PoolError::UnsupportedVersion { version: transaction.version, minimum: TRANSACTION_VERSION });
+        }
+        // relayers cannot relabel a transaction, the id has to match what was signed
+        if !transaction.has_valid_id() {
+            return Err(PoolError::IdMismatch { expected: transaction.compute_id() });
+        }
+        if input.chain_id != blockchain.chain_id {
+            return Err(PoolError::WrongChain { expected: blockchain.chain_id, found: input.chain_id });
+        }
+        transaction.verify().map_err(|reason| PoolError::Invalid { reason })?;
+        // funds paid to anything but a public key could never be spent again
+        if let Some(output) = transaction.outputs.iter().find(|output| Address::from_public_key_hex(&output.address, input.chain_id).is_err()) {
+            return Err(PoolError::InvalidRecipient { address: output.address.clone() });
+        }
+        let last_nonce = blockchain.last_nonce(&input.address);
+        if input.nonce <= last_nonce {
+            return Err(PoolError::StaleNonce { last: last_nonce, nonce: input.nonce });
+        }
+        let balance = Wallet::address_balance(&input.address, blockchain);
+        if input.amount > balance {
+            return Err(PoolError::InsufficientBalance { balance, amount: input.amount });
+        }
+        // a sender has at most one pending transaction, which can only be replaced by outbidding it
+        let Some(pending) = self.by_sender.get(&input.address).and_then(|id| self.get(id)) else {
+            return Ok(None);
+        };
+        if pending.input.as_ref().map(|pending_input| (pending_input.amount, pending_input.nonce)) != Some((input.amount, input.nonce)) {
+            return Err(PoolError::ReplacementInputMismatch { pending: pending.id });
+        }
+        if transaction.fee() <= pending.fee() {
+            return Err(PoolError::ReplacementFeeTooLow { pending: pending.id, fee: transaction.fee(), minimum: pending.fee() + 1 });
+        }
+        Ok(Some(pending.id))
+    }
+
+    /// Evicts the entries with the lowest fee rate until the transaction fits, unless it pays less than all of them.
+    fn make_room(&mut self, transaction: &Transaction, size: usize, replaces: Option<TransactionId>) -> Result<(), PoolError> {
+        let fee = transaction.fee();
+        let replaced_size = replaces.and_then(|id| self.entries.get(&id)).map_or(0, |entry| entry.size);
+        let mut count = self.entries.len() + usize::from(replaced_size == 0);
+        let mut bytes = self.total_bytes - replaced_size + size;
+        if size > self.limits.max_bytes {
+            return Err(PoolError::PoolFull);
+        }
+
+        let mut evicted = vec![];
+        let mut candidates = self.entries.values()
+            .filter(|entry| Some(entry.transaction.id) != replaces)
+            .collect::<Vec<_>>();
+        candidates.sort_by(|a, b| a.cmp_fee_rate(b.transaction.fee(), b.size));
+        let mut candidates = candidates.into_iter();
+        while count > self.limits.max_transactions || bytes > self.limits.max_bytes {
+            match candidates.next() {
+                Some(lowest) if lowest.cmp_fee_rate(fee, size) == Ordering::Less => {
+                    count -= 1;
+                    bytes -= lowest.size;
+                    evicted.push(lowest.transaction.id);
+                }
+                _ => return Err(PoolError::PoolFull),
+            }
+        }
+        for id in evicted {
+            println!("Evicting transaction {id} from the full pool");
+            self.remove(&id);
+        }
+        Ok(())
+    }
+
+    fn remove(&mut self, id: &TransactionId) -> Option<Transaction> {
+        let entry = self.entries.remove(id)?;
+        self.total_bytes -= entry.size;
+        if let Some(input) = &entry.transaction.input {
+            if self.by_sender.get(&input.address) == Some(id) {
+                self.by_sender.remove(&input.address);
+            }
+        }
+        Some(entry.transaction)
+    }
+
+    /// Drops the transactions that have waited longer than the expiry, returning their ids.
+    pub fn expire(&mut self, now: DateTime<Utc>) -> Vec<TransactionId> {
+        let expired = self.entries.values()
+            .filter(|entry| now - entry.added_at > self.limits.expiry)
+            .map(|entry| entry.transaction.id)
+            .collect::<Vec<_>>();
+        for id in &expired {
+            self.remove(id);
+        }
+        expired
     }
 
     pub fn existing_transaction(&self, address: &str) -> Option<Transaction> {
-        self.transactions.iter().find(|t| t.input.as_ref().unwrap().address == address).cloned()
+        self.by_sender.get(address).and_then(|id| self.get(id)).cloned()
     }
 
-    pub fn valid_transactions(&self) -> Vec<Transaction> {
-        self.transactions.iter().filter(|t| {
-            // reduce output amount to total amount
-            let total_output_amount: u64 = t.outputs.iter().map(|o| o.amount).sum();
-            if total_output_amount != t.input.as_ref().unwrap().amount {
-                return false;
+    /// What pending transactions move in and out of a set of addresses, as `(incoming, outgoing)`. Incoming is what
+    /// other senders pay to them, outgoing what they pay to others including fees. Change and transfers within
+    /// the set count as neither.
+    pub fn pending_amounts(&self, is_own: impl Fn(&str) -> bool) -> (u64, u64) {
+        let mut incoming = 0u64;
+        let mut outgoing = 0u64;
+        for entry in self.entries.values() {
+            let Some(input) = entry.transaction.input.as_ref() else { continue };
+            let own_outputs = entry.transaction.outputs.iter()
+                .filter(|output| is_own(&output.address))
+                .fold(0u64, |total, output| total.saturating_add(output.amount));
+            if is_own(&input.address) {
+                outgoing = outgoing.saturating_add(input.amount.saturating_sub(own_outputs));
+            } else {
+                incoming = incoming.saturating_add(own_outputs);
             }
-            // verify signature
-            if !t.verify() {
-                return false;
+        }
+        (incoming, outgoing)
+    }
+
+    /// Drops the transactions that were included in the given blocks, and the pending transactions whose nonce
+    /// those blocks used up. Everything else stays pending.
+    pub fn remove_confirmed(&mut self, blocks: &[Block]) {
+        let confirmed = blocks.iter()
+            .flat_map(|block| block.data.iter())
+            .collect::<Vec<_>>();
+        for transaction in &confirmed {
+            self.remove(&transaction.id);
+        }
+        let stale = confirmed.iter()
+            .filter(|transaction| !transaction.is_reward())
+            .filter_map(|transaction| transaction.input.as_ref())
+            .filter_map(|input| self.existing_transaction(&input.address)
+                .filter(|pending| pending.nonce().is_some_and(|nonce| nonce <= input.nonce)))
+            .map(|pending| pending.id)
+            .collect::<HashSet<_>>();
+        for id in stale {
+            self.remove(&id);
+        }
+    }
+
+    /// Puts the transactions of blocks that a reorganisation disconnected back into the pool, oldest first. Those the
+    /// new chain already includes, or no longer allows, are refused by the usual admission checks.
+    pub fn readd_disconnected(&mut self, disconnected: &[Block], blockchain: &Blockchain) {
+        for transaction in disconnected.iter().flat_map(|block| block.data.iter()).filter(|transaction| !transaction.is_reward()) {
+            if self.update_or_add_transaction(transaction.clone(), blockchain).is_ok() {
+                println!("Transaction {} is pending again after a reorganisation", transaction.id);
             }
-            true
-        }).cloned().collect()
+        }
+    }
+
+    pub fn valid_transactions(&self) -> Vec<Transaction> {
+        self.transactions().into_iter().filter(|t| t.verify().is_ok()).collect()
+    }
+}
+
+impl fmt::Display for PoolError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PoolError::Malformed { message } => write!(f, "transaction is malformed: {message}"),
+            PoolError::Invalid { reason } => write!(f, "{reason}"),
+            PoolError::UnsupportedVersion { version, minimum } => write!(f, "transaction version {version} is no longer accepted, at least {minimum} is required"),
+            PoolError::IdMismatch { expected } => write!(f, "transaction id does not match its contents, expected {expected}"),
+            PoolError::WrongChain { expected, found } => write!(f, "transaction is signed for chain {found}, expected chain {expected}"),
+            PoolError::InvalidRecipient { address } => write!(f, "'{address}' is not a public key and cannot receive funds"),
+            PoolError::InsufficientBalance { balance, amount } => write!(f, "amount {amount} exceeds balance {balance}"),
+            PoolError::StaleNonce { last, nonce } => write!(f, "nonce {nonce} was already used, the sender's last nonce is {last}"),
+            PoolError::ReplacementInputMismatch { pending } => write!(f, "replacement of {pending} must spend the same input with the same nonce"),
+            PoolError::ReplacementFeeTooLow { pending, fee, minimum } => write!(f, "replacement of {pending} pays a fee of {fee}, at least {minimum} is required"),
+            PoolError::AlreadyReplaced { by } => write!(f, "transaction was already replaced by {by}"),
+            PoolError::PoolFull => write!(f, "transaction pool is full and the transaction pays too low a fee rate"),
+        }
     }
-}
\ No newline at end of file
+}
+
+impl Error for PoolError {}
@@ -1,12 +1,18 @@
 use std::fmt;
+use serde::Serialize;
 use crate::utils::config::INITIAL_BALANCE;
 use ecdsa::{SigningKey, VerifyingKey};
 use ecdsa::signature::{Signer, Verifier};
 use k256::{Secp256k1};
 use k256::{ecdsa::Signature as K256Signature};
-use crate::blockchain::blockchain::Blockchain;
-use crate::wallet::transaction::Transaction;
-use crate::wallet::transaction_pool::TransactionPool;
+use crate::blockchain::block::Block;
+use crate::blockchain::blockchain::{Blockchain, DEFAULT_CHAIN_ID};
+use crate::wallet::address::Address;
+use crate::wallet::keystore::KeystoreError;
+use crate::wallet::transaction::{Transaction, TxError, UnsignedTransaction};
+use crate::wallet::transaction_pool::{PoolError, TransactionPool};
+
+pub const BLOCKCHAIN_WALLET_ADDRESS: &str = "blockchain_wallet";
 
 #[derive(Clone)]
 pub struct Wallet {
@@ -14,6 +20,20 @@ pub struct Wallet {
     pub signing_key: SigningKey<Secp256k1>,
     pub verifying_key: VerifyingKey<Secp256k1>,
     pub public_key: String,
+    pub chain_id: u32,
+    /// Nonce the next new transaction is signed with.
+    pub nonce: u64,
+}
+
+/// A balance split by how settled the funds are.
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct BalanceReport {
+    /// Balance as of the tip of the chain.
+    pub confirmed: u64,
+    /// What can be paid out now: confirmed funds buried deep enough, less what pending transactions already spend.
+    pub spendable: u64,
+    pub pending_incoming: u64,
+    pub pending_outgoing: u64,
 }
 
 
@@ -25,88 +45,152 @@ impl fmt::Display for Wallet {
 }
 
 
+impl Default for Wallet {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl Wallet {
     pub fn new() -> Wallet {
-        let signing_key = SigningKey::random(&mut rand::thread_rng());
+        Wallet::from_signing_key(SigningKey::random(&mut rand::thread_rng()))
+    }
+
+    /// Restores a wallet from its raw 32 byte secret key, as exported by `secret_key`.
+    pub fn from_secret_key(secret_key: &[u8]) -> Result<Wallet, KeystoreError> {
+        let signing_key = SigningKey::from_slice(secret_key).map_err(|_| KeystoreError::InvalidKey)?;
+        Ok(Wallet::from_signing_key(signing_key))
+    }
+
+    pub fn from_secret_key_hex(secret_key: &str) -> Result<Wallet, KeystoreError> {
+        let secret_key = hex::decode(secret_key.trim()).map_err(|_| KeystoreError::InvalidKey)?;
+        Wallet::from_secret_key(&secret_key)
+    }
+
+    pub fn secret_key(&self) -> Vec<u8> {
+        self.signing_key.to_bytes().to_vec()
+    }
+
+    pub fn secret_key_hex(&self) -> String {
+        hex::encode(self.secret_key())
+    }
+
+    pub(crate) fn from_signing_key(signing_key: SigningKey<Secp256k1>) -> Wallet {
         let binding = signing_key.clone();
         let verifying_key = binding.verifying_key();
-        let public_key = hex::encode(&verifying_key.to_sec1_bytes());
+        let public_key = hex::encode(verifying_key.to_sec1_bytes());
         Wallet {
             balance: INITIAL_BALANCE,
             public_key,
             signing_key,
             verifying_key: *verifying_key,
+            chain_id: DEFAULT_CHAIN_ID,
+            nonce: 1,
         }
     }
 
+    /// The checksummed form of `public_key`, with the prefix of the wallet's chain.
+    pub fn address(&self) -> Address {
+        Address::from_public_key(&self.verifying_key, self.chain_id)
+    }
+
     pub fn sign(&self, data: &str) -> String {
         let signature: K256Signature = self.signing_key.sign(data.as_bytes());
         hex::encode(signature.to_der().as_bytes())
     }
 
-    pub fn verify(address: VerifyingKey<Secp256k1>, data: &str, signature: &str) -> bool {
-        let signature_bytes = hex::decode(signature).unwrap();
-        let signature = K256Signature::from_der(&signature_bytes).unwrap();
-        address.verify(data.as_bytes(), &signature).is_ok()
+    pub fn verify(address: VerifyingKey<Secp256k1>, data: &str, signature: &str) -> Result<(), TxError> {
+        let signature_bytes = hex::decode(signature).map_err(|_| TxError::InvalidSignatureEncoding)?;
+        let signature = K256Signature::from_der(&signature_bytes).map_err(|_| TxError::InvalidSignatureEncoding)?;
+        address.verify(data.as_bytes(), &signature).map_err(|_| TxError::SignatureMismatch)
     }
 
-    pub fn create_transaction(&mut self, recipient: String, amount: u64, transaction_pool: &mut TransactionPool, blockchain: &Blockchain) -> Result<Transaction, &'static str> {
+    pub fn create_transaction(&mut self, recipient: String, amount: u64, transaction_pool: &mut TransactionPool, blockchain: &Blockchain) -> Result<Transaction, PoolError> {
         self.balance = self.calculate_balance(blockchain);
-        if amount > self.balance {
-            return Err("Amount exceeds balance");
-        }
-        let transaction = transaction_pool.existing_transaction(&self.public_key);
-
-        let updated_transaction : Transaction;
-        if let Some(mut existing_transaction) = transaction {
-            updated_transaction = existing_transaction.update(self, recipient, amount).unwrap();
-            transaction_pool.update_or_add_transaction(updated_transaction.clone());
-        } else {
-            updated_transaction = Transaction::new(self, recipient, amount);
-            transaction_pool.update_or_add_transaction(updated_transaction.clone());
-        }
+        self.nonce = self.next_nonce(transaction_pool, blockchain);
+        let transaction = UnsignedTransaction::build(&self.public_key, self.chain_id, recipient, amount, transaction_pool, blockchain)?
+            .sign(self)
+            .map_err(|reason| PoolError::Invalid { reason })?;
+        transaction_pool.update_or_add_transaction(transaction.clone(), blockchain)?;
+
+        Ok(transaction)
+    }
 
-        Ok(updated_transaction)
+    /// One past the highest nonce used by this wallet, on the chain or by its pending transaction.
+    pub fn next_nonce(&self, transaction_pool: &TransactionPool, blockchain: &Blockchain) -> u64 {
+        let pending_nonce = transaction_pool.existing_transaction(&self.public_key)
+            .and_then(|transaction| transaction.nonce())
+            .unwrap_or(0);
+        blockchain.last_nonce(&self.public_key).max(pending_nonce) + 1
     }
 
     pub fn blockchain_wallet() -> Wallet {
         let mut blockchain_wallet = Wallet::new();
-        blockchain_wallet.public_key = "blockchain_wallet".to_string();
+        blockchain_wallet.public_key = BLOCKCHAIN_WALLET_ADDRESS.to_string();
         blockchain_wallet
     }
 
     pub fn calculate_balance(&self, blockchain: &Blockchain) -> u64 {
-        let mut balance = self.balance;
+        Wallet::balance_from(self.balance, &self.public_key, &blockchain.chain)
+    }
+
+    /// Balance of any address on the chain, assuming it started with the initial balance.
+    pub fn address_balance(address: &str, blockchain: &Blockchain) -> u64 {
+        Wallet::balance_from(INITIAL_BALANCE, address, &blockchain.chain)
+    }
+
+    /// What other senders paid to `address` in blocks with fewer than `confirmations` confirmations, the tip having one,
+    /// as far as the confirmed balance counts it.
+    pub fn immature_incoming(address: &str, blockchain: &Blockchain, confirmations: usize) -> u64 {
+        let mature = blockchain.chain.len().saturating_sub(confirmations.saturating_sub(1)).max(1);
+        let since = Wallet::latest_spend(address, &blockchain.chain).map_or(0, |transaction| transaction.input.as_ref().unwrap().timestamp.timestamp());
+        blockchain.chain[mature..].iter()
+            .flat_map(|block| block.data.iter())
+            .filter_map(|transaction| transaction.input.as_ref().map(|input| (input, transaction)))
+            .filter(|(input, _)| input.address != address && input.timestamp.timestamp() > since)
+            .flat_map(|(_, transaction)| transaction.outputs.iter())
+            .filter(|output| output.address == address)
+            .fold(0u64, |total, output| total.saturating_add(output.amount))
+    }
+
+    /// Confirmed, pending and spendable balance of `address`, where funds need `confirmations` confirmations to be spent.
+    pub fn balance_report(address: &str, blockchain: &Blockchain, transaction_pool: &TransactionPool, confirmations: usize) -> BalanceReport {
+        let (pending_incoming, pending_outgoing) = transaction_pool.pending_amounts(|own| own == address);
+        BalanceReport::new(Wallet::address_balance(address, blockchain), Wallet::immature_incoming(address, blockchain, confirmations),
+                           pending_incoming, pending_outgoing)
+    }
+
+    /// The address's most recent transaction, the balance is its change plus whatever was received since.
+    fn latest_spend<'a>(address: &str, chain: &'a [Block]) -> Option<&'a Transaction> {
+        chain.iter()
+            .flat_map(|block| block.data.iter())
+            .filter(|transaction| transaction.input.as_ref().is_some_and(|input| input.address == address))
+            .max_by_key(|transaction| transaction.input.as_ref().unwrap().timestamp.timestamp())
+    }
+
+    fn balance_from(initial_balance: u64, address: &str, chain: &[Block]) -> u64 {
+        let mut balance = initial_balance;
         let mut transactions: Vec<Transaction> = Vec::new();
 
-        blockchain.chain.iter().for_each(|block| {
+        chain.iter().for_each(|block| {
             block.data.iter().for_each(|transaction| {
                 transactions.push(transaction.clone());
             });
         });
 
-        let wallet_input_transactions = transactions.iter().filter(|transaction|
-            transaction.input.is_some() && transaction.input.as_ref().unwrap().address == self.public_key
-        ).collect::<Vec<&Transaction>>();
-
-        println!("transactions: {:?}", transactions);
-
         let mut start_time = 0;
-        if wallet_input_transactions.len() > 0 {
-            let recent_input_transaction = wallet_input_transactions.iter()
-                .max_by_key(|transaction|
-                    transaction.input.as_ref().unwrap().timestamp.timestamp()
-                );
-            start_time = recent_input_transaction.unwrap().input.as_ref().unwrap().timestamp.timestamp();
-            balance = recent_input_transaction.unwrap().outputs.iter().find(|output|
-                output.address == self.public_key
-            ).unwrap().amount;
+        if let Some(recent_input_transaction) = Wallet::latest_spend(address, chain) {
+            start_time = recent_input_transaction.input.as_ref().unwrap().timestamp.timestamp();
+            // a transaction may spend everything without paying change back to the sender
+            balance = recent_input_transaction.outputs.iter().find(|output|
+                output.address == address
+            ).map_or(0, |output| output.amount);
         }
 
         transactions.iter().for_each(|transaction| {
             if transaction.input.as_ref().unwrap().timestamp.timestamp() > start_time {
                 transaction.outputs.iter().for_each(|output| {
-                    if output.address == self.public_key {
+                    if output.address == address {
                         balance += output.amount;
                     }
                 });
@@ -120,3 +204,15 @@ impl Wallet {
 
 }
 
+impl BalanceReport {
+    /// Funds received in blocks that are not buried deep enough yet are not spendable, and neither is anything
+    /// a pending transaction already spends.
+    pub fn new(confirmed: u64, immature_incoming: u64, pending_incoming: u64, pending_outgoing: u64) -> BalanceReport {
+        BalanceReport {
+            confirmed,
+            spendable: confirmed.saturating_sub(immature_incoming).saturating_sub(pending_outgoing),
+            pending_incoming,
+            pending_outgoing,
+        }
+    }
+}
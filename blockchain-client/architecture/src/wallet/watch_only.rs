@@ -0,0 +1,105 @@
+use std::collections::BTreeMap;
+use crate::blockchain::blockchain::Blockchain;
+use crate::wallet::address::Address;
+use crate::wallet::hd_wallet::is_used;
+use crate::wallet::transaction::Transaction;
+use crate::wallet::transaction_pool::TransactionPool;
+use crate::wallet::wallet::{BalanceReport, Wallet};
+
+/// Tracks addresses whose keys the node does not hold, such as cold storage or a customer's wallet.
+/// It is built from the addresses alone, so it can report balances and history but never sign; spending
+/// goes through an unsigned transaction signed wherever the key is kept.
+#[derive(Debug, Clone, PartialEq)]
+pub struct WatchOnlyWallet {
+    addresses: Vec<Address>,
+    /// Confirmed balance of every watched address, keyed by hex public key, as of the last scan.
+    balances: BTreeMap<String, u64>,
+    /// Confirmed transactions involving any watched address, oldest first, as of the last scan.
+    history: Vec<Transaction>,
+    /// Hash of the tip the chain was last scanned up to.
+    scanned_tip: Option<String>,
+}
+
+impl WatchOnlyWallet {
+    /// Watches the given addresses, ignoring duplicates. Nothing is known about them until the first scan.
+    pub fn new(addresses: Vec<Address>) -> WatchOnlyWallet {
+        let mut unique: Vec<Address> = Vec::with_capacity(addresses.len());
+        for address in addresses {
+            if !unique.contains(&address) {
+                unique.push(address);
+            }
+        }
+        WatchOnlyWallet {
+            addresses: unique,
+            balances: BTreeMap::new(),
+            history: vec![],
+            scanned_tip: None,
+        }
+    }
+
+    pub fn addresses(&self) -> &[Address] {
+        &self.addresses
+    }
+
+    /// Whether `address`, a hex public key as used in transactions, is one of the watched ones.
+    pub fn watches(&self, address: &str) -> bool {
+        self.addresses.iter().any(|watched| watched.public_key_hex() == address)
+    }
+
+    /// Rebuilds balances and history from the whole chain, which also undoes anything a reorganisation dropped.
+    /// Addresses that never appear on the chain hold nothing, as for an `HdWallet`.
+    pub fn rescan(&mut self, blockchain: &Blockchain) {
+        let public_keys = self.addresses.iter().map(Address::public_key_hex).collect::<Vec<_>>();
+        self.balances = public_keys.iter()
+            .map(|public_key| (public_key.clone(), if is_used(public_key, blockchain) { Wallet::address_balance(public_key, blockchain) } else { 0 }))
+            .collect();
+        self.history = blockchain.chain.iter()
+            .flat_map(|block| block.data.iter())
+            .filter(|transaction| public_keys.iter().any(|public_key| transaction.involves(public_key)))
+            .cloned()
+            .collect();
+        self.scanned_tip = blockchain.chain.last().map(|block| block.hash.clone());
+    }
+
+    /// Rescans only if the chain moved since the last scan.
+    pub fn sync(&mut self, blockchain: &Blockchain) {
+        if self.scanned_tip.as_ref() != blockchain.chain.last().map(|block| &block.hash) {
+            self.rescan(blockchain);
+        }
+    }
+
+    /// Confirmed balance over all watched addresses.
+    pub fn balance(&self) -> u64 {
+        self.balances.values().sum()
+    }
+
+    pub fn balance_of(&self, address: &Address) -> u64 {
+        self.balances.get(&address.public_key_hex()).copied().unwrap_or_default()
+    }
+
+    pub fn history(&self) -> &[Transaction] {
+        &self.history
+    }
+
+    /// Pending transactions involving any watched address.
+    pub fn pending(&self, transaction_pool: &TransactionPool) -> Vec<Transaction> {
+        transaction_pool.transactions().into_iter()
+            .filter(|transaction| self.addresses.iter().any(|address| transaction.involves(&address.public_key_hex())))
+            .collect()
+    }
+
+    /// What pending transactions from other senders pay to the watched addresses. Change a watched sender
+    /// keeps and transfers between watched addresses do not count, as they do not add to the wallet.
+    pub fn pending_incoming(&self, transaction_pool: &TransactionPool) -> u64 {
+        transaction_pool.pending_amounts(|address| self.watches(address)).0
+    }
+
+    /// Balance over all watched addresses as of the last scan, with funds needing `confirmations` confirmations to be spent.
+    pub fn balance_report(&self, blockchain: &Blockchain, transaction_pool: &TransactionPool, confirmations: usize) -> BalanceReport {
+        let immature_incoming = self.addresses.iter()
+            .map(|address| Wallet::immature_incoming(&address.public_key_hex(), blockchain, confirmations))
+            .fold(0u64, u64::saturating_add);
+        let (pending_incoming, pending_outgoing) = transaction_pool.pending_amounts(|address| self.watches(address));
+        BalanceReport::new(self.balance(), immature_incoming, pending_incoming, pending_outgoing)
+    }
+}
@@ -0,0 +1,44 @@
+use bech32::{Bech32, Bech32m, Hrp};
+use crate::blockchain::blockchain::DEFAULT_CHAIN_ID;
+use crate::wallet::address::{Address, AddressError, MAIN_PREFIX, TEST_PREFIX};
+use crate::wallet::wallet::Wallet;
+
+#[test]
+fn test_round_trips_through_the_encoding() {
+    let wallet = Wallet::new();
+    let encoded = wallet.address().to_string();
+    assert!(encoded.starts_with(&format!("{MAIN_PREFIX}1")));
+    let address = Address::parse(&encoded, DEFAULT_CHAIN_ID).unwrap();
+    assert_eq!(address.public_key_hex(), wallet.public_key);
+    assert_eq!(Address::from_public_key_hex(&wallet.public_key, DEFAULT_CHAIN_ID).unwrap(), address);
+    assert_eq!(Address::parse(&encoded.to_uppercase(), DEFAULT_CHAIN_ID).unwrap(), address);
+}
+
+#[test]
+fn test_detects_a_typo() {
+    let encoded = Wallet::new().address().to_string();
+    let last = encoded.chars().last().unwrap();
+    let typo = format!("{}{}", &encoded[..encoded.len() - 1], if last == 'q' { 'p' } else { 'q' });
+    assert!(matches!(Address::parse(&typo, DEFAULT_CHAIN_ID), Err(AddressError::InvalidEncoding { .. })));
+    assert!(matches!(Address::parse("recipient", DEFAULT_CHAIN_ID), Err(AddressError::InvalidEncoding { .. })));
+}
+
+#[test]
+fn test_rejects_addresses_of_another_network() {
+    let mut wallet = Wallet::new();
+    wallet.chain_id = DEFAULT_CHAIN_ID + 1;
+    let encoded = wallet.address().to_string();
+    assert!(encoded.starts_with(&format!("{TEST_PREFIX}1")));
+    assert_eq!(Address::parse(&encoded, DEFAULT_CHAIN_ID),
+               Err(AddressError::WrongNetwork { expected: MAIN_PREFIX.to_string(), found: TEST_PREFIX.to_string() }));
+}
+
+#[test]
+fn test_rejects_payloads_that_are_not_public_keys() {
+    let hrp = Hrp::parse(MAIN_PREFIX).unwrap();
+    let not_a_key = bech32::encode::<Bech32m>(hrp, &[7u8; 33]).unwrap();
+    assert_eq!(Address::parse(&not_a_key, DEFAULT_CHAIN_ID), Err(AddressError::InvalidPublicKey));
+    // the original bech32 checksum is not accepted in place of bech32m
+    let bech32 = bech32::encode::<Bech32>(hrp, &hex::decode(Wallet::new().public_key).unwrap()).unwrap();
+    assert!(matches!(Address::parse(&bech32, DEFAULT_CHAIN_ID), Err(AddressError::InvalidEncoding { .. })));
+}
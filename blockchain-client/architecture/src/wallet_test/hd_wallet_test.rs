@@ -0,0 +1,69 @@
+use crate::blockchain::blockchain::Blockchain;
+use crate::utils::config::{INITIAL_BALANCE, MINING_REWARD};
+use crate::wallet::hd_wallet::{HdWallet, HdWalletError, GAP_LIMIT};
+use crate::wallet::transaction::Transaction;
+use crate::wallet::transaction_pool::TransactionPool;
+use crate::wallet::wallet::Wallet;
+
+const PHRASE: &str = "abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon \
+    abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon art";
+
+#[test]
+fn test_derives_the_same_addresses_from_the_same_phrase() {
+    let mut generated = HdWallet::generate();
+    assert_eq!(generated.phrase().split_whitespace().count(), 24);
+    let restored = HdWallet::from_phrase(generated.phrase(), "").unwrap();
+    assert_eq!(generated.next_address().public_key, restored.derive(0).public_key);
+    assert_eq!(generated.next_address().public_key, restored.derive(1).public_key);
+    assert_ne!(restored.derive(0).public_key, restored.derive(1).public_key);
+}
+
+#[test]
+fn test_passphrase_changes_every_key() {
+    let plain = HdWallet::from_phrase(PHRASE, "").unwrap();
+    let protected = HdWallet::from_phrase(PHRASE, "secret").unwrap();
+    assert_ne!(plain.derive(0).public_key, protected.derive(0).public_key);
+}
+
+#[test]
+fn test_rejects_an_invalid_phrase() {
+    assert_eq!(HdWallet::from_phrase("abandon abandon abandon", "").err(), Some(HdWalletError::InvalidMnemonic));
+    assert_eq!(HdWallet::from_phrase(&PHRASE.replace("art", "abandon"), "").err(), Some(HdWalletError::InvalidMnemonic));
+}
+
+#[test]
+fn test_recovers_addresses_and_aggregates_their_balance() {
+    let hd_wallet = HdWallet::from_phrase(PHRASE, "").unwrap();
+    let mut blockchain = Blockchain::new();
+    blockchain.add_block(vec![Transaction::reward_transaction(&hd_wallet.derive(0), &Wallet::blockchain_wallet())]);
+    blockchain.add_block(vec![Transaction::reward_transaction(&hd_wallet.derive(5), &Wallet::blockchain_wallet())]);
+    // beyond the gap limit, so recovery never looks that far
+    blockchain.add_block(vec![Transaction::reward_transaction(&hd_wallet.derive(6 + GAP_LIMIT), &Wallet::blockchain_wallet())]);
+
+    let recovered = HdWallet::recover(PHRASE, "", &blockchain).unwrap();
+    assert_eq!(recovered.address_count, 6);
+    assert_eq!(recovered.calculate_balance(&blockchain), 2 * INITIAL_BALANCE + 2 * MINING_REWARD);
+}
+
+#[test]
+fn test_handing_out_addresses_does_not_add_to_the_balance() {
+    let mut hd_wallet = HdWallet::from_phrase(PHRASE, "").unwrap();
+    let blockchain = Blockchain::new();
+    assert_eq!(hd_wallet.calculate_balance(&blockchain), 0);
+    hd_wallet.next_address();
+    hd_wallet.next_address();
+    assert_eq!(hd_wallet.calculate_balance(&blockchain), 0);
+}
+
+#[test]
+fn test_reports_the_balance_of_every_address() {
+    let mut hd_wallet = HdWallet::from_phrase(PHRASE, "").unwrap();
+    hd_wallet.address_count = 3;
+    let mut blockchain = Blockchain::new();
+    blockchain.add_block(vec![Transaction::reward_transaction(&hd_wallet.derive(2), &Wallet::blockchain_wallet())]);
+    let mut transaction_pool = TransactionPool::new();
+    transaction_pool.update_or_add_transaction(Transaction::new(&Wallet::new(), hd_wallet.derive(1).public_key, 30).unwrap(), &blockchain).unwrap();
+
+    let report = hd_wallet.balance_report(&blockchain, &transaction_pool, 2);
+    assert_eq!((report.confirmed, report.spendable, report.pending_incoming), (INITIAL_BALANCE + MINING_REWARD, INITIAL_BALANCE, 30));
+}
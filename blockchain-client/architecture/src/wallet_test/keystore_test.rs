@@ -0,0 +1,80 @@
+use std::env;
+use std::fs;
+use crate::wallet::hd_wallet::HdWallet;
+use crate::wallet::keystore::{Keystore, KeystoreError, KeystoreKind};
+use crate::wallet::wallet::Wallet;
+
+#[test]
+fn test_decrypts_with_the_right_password() {
+    let wallet = Wallet::new();
+    let keystore = Keystore::encrypt(&wallet, "correct horse");
+    assert_eq!(keystore.address, wallet.public_key);
+    assert!(!keystore.ciphertext.contains(&wallet.secret_key_hex()));
+    assert_eq!(keystore.decrypt("correct horse").unwrap().public_key, wallet.public_key);
+    assert_eq!(keystore.decrypt("battery staple").err(), Some(KeystoreError::WrongPassword));
+}
+
+#[test]
+fn test_detects_a_swapped_address() {
+    let mut keystore = Keystore::encrypt(&Wallet::new(), "password");
+    keystore.address = Wallet::new().public_key;
+    assert_eq!(keystore.decrypt("password").err(), Some(KeystoreError::WrongPassword));
+}
+
+#[test]
+fn test_persists_the_wallet_across_restarts() {
+    let path = env::temp_dir().join(format!("rustchain-keystore-{}", std::process::id())).join("wallet.json");
+    let _ = fs::remove_file(&path);
+    let created = Keystore::load_or_create(&path, "password").unwrap();
+    let loaded = Keystore::load_or_create(&path, "password").unwrap();
+    assert_eq!(loaded.public_key, created.public_key);
+    // an existing keystore is never overwritten
+    assert!(Keystore::encrypt(&Wallet::new(), "password").save(&path).is_err());
+    assert_eq!(Keystore::load_or_create(&path, "wrong").err(), Some(KeystoreError::WrongPassword));
+    fs::remove_dir_all(path.parent().unwrap()).unwrap();
+}
+
+#[test]
+fn test_imports_and_exports_raw_secret_keys() {
+    let wallet = Wallet::new();
+    let imported = Wallet::from_secret_key_hex(&format!("{}\n", wallet.secret_key_hex())).unwrap();
+    assert_eq!(imported.public_key, wallet.public_key);
+    assert_eq!(Wallet::from_secret_key_hex("not hex").err(), Some(KeystoreError::InvalidKey));
+    assert_eq!(Wallet::from_secret_key(&[0u8; 32]).err(), Some(KeystoreError::InvalidKey));
+}
+
+#[test]
+fn test_seals_the_seed_of_an_hd_wallet() {
+    let mut hd_wallet = HdWallet::generate();
+    hd_wallet.address_count = 4;
+    let keystore = Keystore::encrypt_hd(&hd_wallet, "password");
+    assert_eq!(keystore.kind, KeystoreKind::HdSeed);
+    assert_eq!(keystore.address, hd_wallet.derive(0).public_key);
+
+    let restored = keystore.decrypt_hd("password").unwrap();
+    assert_eq!(restored.phrase(), hd_wallet.phrase());
+    assert_eq!(restored.address_count, 4);
+    assert_eq!(restored.derive(3).public_key, hd_wallet.derive(3).public_key);
+    // the node mines to the first address
+    assert_eq!(keystore.decrypt("password").unwrap().public_key, hd_wallet.derive(0).public_key);
+    assert_eq!(keystore.decrypt_hd("wrong").err(), Some(KeystoreError::WrongPassword));
+}
+
+#[test]
+fn test_keeps_the_passphrase_of_an_hd_wallet() {
+    let hd_wallet = HdWallet::from_phrase(HdWallet::generate().phrase(), "extra words").unwrap();
+    let restored = Keystore::encrypt_hd(&hd_wallet, "password").decrypt_hd("password").unwrap();
+    assert_eq!(restored.derive(0).public_key, hd_wallet.derive(0).public_key);
+}
+
+#[test]
+fn test_single_key_keystores_hold_no_hd_wallet() {
+    let keystore = Keystore::encrypt(&Wallet::new(), "password");
+    assert_eq!(keystore.kind, KeystoreKind::Key);
+    assert_eq!(keystore.decrypt_hd("password").err(), Some(KeystoreError::NotHdWallet));
+    // keystores written before HD wallets existed have no kind
+    let mut json = serde_json::to_value(&keystore).unwrap();
+    json.as_object_mut().unwrap().remove("kind");
+    assert_eq!(serde_json::from_value::<Keystore>(json).unwrap().decrypt("password").unwrap().public_key,
+               keystore.decrypt("password").unwrap().public_key);
+}
@@ -1,6 +1,15 @@
 #[cfg(test)]
+#[allow(clippy::module_inception)]
 mod wallet_test;
 #[cfg(test)]
 mod transaction_test;
 #[cfg(test)]
-mod transaction_pool_test;
\ No newline at end of file
+mod transaction_pool_test;
+#[cfg(test)]
+mod keystore_test;
+#[cfg(test)]
+mod hd_wallet_test;
+#[cfg(test)]
+mod address_test;
+#[cfg(test)]
+mod watch_only_test;
\ No newline at end of file
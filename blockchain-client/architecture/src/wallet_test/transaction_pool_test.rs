@@ -1,26 +1,264 @@
-use crate::wallet::transaction::Transaction;
-use crate::wallet::transaction_pool::TransactionPool;
+use crate::blockchain::blockchain::Blockchain;
+use chrono::{TimeDelta, Utc};
+use crate::wallet::transaction::{Transaction, TransactionId, TransactionOutput, TxError, UnsignedTransaction, LEGACY_TRANSACTION_VERSION, TRANSACTION_VERSION};
+use crate::wallet::transaction_pool::{PoolError, PoolLimits, TransactionPool};
 use crate::wallet::wallet::Wallet;
 
+fn transaction_with_fee(fee: u64) -> Transaction {
+    let wallet = Wallet::new();
+    let outputs = vec![
+        TransactionOutput::new(wallet.balance - 50 - fee, wallet.public_key.clone()),
+        TransactionOutput::new(50, Wallet::new().public_key),
+    ];
+    Transaction::transaction_with_outputs(&wallet, outputs)
+}
+
+fn limits(max_transactions: usize) -> PoolLimits {
+    PoolLimits { max_transactions, ..PoolLimits::default() }
+}
+
 #[test]
 fn test_add_transaction_to_the_pool() {
     let mut transaction_pool = TransactionPool::new();
-    let transaction = Transaction::new(&Wallet::new(), "recipient".to_string(), 50);
-    transaction_pool.update_or_add_transaction(transaction.clone());
-    assert_eq!(transaction_pool.transactions.len(), 1);
-    assert_eq!(transaction_pool.transactions[0], transaction);
+    let transaction = Transaction::new(&Wallet::new(), Wallet::new().public_key, 50).unwrap();
+    transaction_pool.update_or_add_transaction(transaction.clone(), &Blockchain::new()).unwrap();
+    assert_eq!(transaction_pool.len(), 1);
+    assert_eq!(transaction_pool.transactions()[0], transaction);
+}
+
+
+#[test]
+fn test_replace_transaction_in_the_pool() {
+    let mut transaction_pool = TransactionPool::new();
+    let wallet = Wallet::new();
+    let transaction = Transaction::new(&wallet, Wallet::new().public_key, 50).unwrap();
+    transaction_pool.update_or_add_transaction(transaction.clone(), &Blockchain::new()).unwrap();
+    let replacement = transaction.replace(&wallet, Wallet::new().public_key, 25, 1).expect("Failed to replace transaction");
+    transaction_pool.update_or_add_transaction(replacement.clone(), &Blockchain::new()).unwrap();
+    assert_eq!(transaction_pool.len(), 1);
+    assert_eq!(transaction_pool.transactions()[0], replacement);
+    assert_eq!(transaction_pool.replaced_by(&transaction.id), Some(replacement.id));
+    assert_eq!(transaction_pool.update_or_add_transaction(transaction, &Blockchain::new()), Err(PoolError::AlreadyReplaced { by: replacement.id }));
 }
 
+#[test]
+fn test_rejects_an_in_place_update_of_a_pending_transaction() {
+    let mut transaction_pool = TransactionPool::new();
+    let wallet = Wallet::new();
+    let mut transaction = Transaction::new(&wallet, Wallet::new().public_key, 50).unwrap();
+    let pending = transaction.id;
+    transaction_pool.update_or_add_transaction(transaction.clone(), &Blockchain::new()).unwrap();
+    transaction.update(&wallet, Wallet::new().public_key, 25).expect("Failed to update transaction");
+    assert_ne!(transaction.id, pending);
+    assert_eq!(transaction_pool.update_or_add_transaction(transaction.clone(), &Blockchain::new()),
+               Err(PoolError::ReplacementFeeTooLow { pending, fee: 0, minimum: 1 }));
+}
+
+#[test]
+fn test_accepts_a_pending_transaction_again() {
+    let mut transaction_pool = TransactionPool::new();
+    let transaction = Transaction::new(&Wallet::new(), Wallet::new().public_key, 50).unwrap();
+    transaction_pool.update_or_add_transaction(transaction.clone(), &Blockchain::new()).unwrap();
+    transaction_pool.update_or_add_transaction(transaction.clone(), &Blockchain::new()).unwrap();
+    assert_eq!(transaction_pool.len(), 1);
+}
+
+
+#[test]
+fn test_removes_only_confirmed_transactions_from_the_pool() {
+    let mut transaction_pool = TransactionPool::new();
+    let confirmed = Transaction::new(&Wallet::new(), Wallet::new().public_key, 50).unwrap();
+    let pending = Transaction::new(&Wallet::new(), Wallet::new().public_key, 50).unwrap();
+    transaction_pool.update_or_add_transaction(confirmed.clone(), &Blockchain::new()).unwrap();
+    transaction_pool.update_or_add_transaction(pending.clone(), &Blockchain::new()).unwrap();
+    let mut blockchain = Blockchain::new();
+    blockchain.add_block(vec![confirmed]);
+    transaction_pool.remove_confirmed(&blockchain.chain);
+    assert_eq!(transaction_pool.transactions(), vec![pending]);
+}
+
+#[test]
+fn test_readds_transactions_of_disconnected_blocks_unless_the_new_chain_includes_them() {
+    let (abandoned, included) = (Transaction::new(&Wallet::new(), Wallet::new().public_key, 50).unwrap(),
+                                 Transaction::new(&Wallet::new(), Wallet::new().public_key, 50).unwrap());
+    let mut old_blockchain = Blockchain::new();
+    old_blockchain.add_block(vec![abandoned.clone(), included.clone(), Transaction::reward_transaction(&Wallet::new(), &Wallet::blockchain_wallet())]);
+    let mut new_blockchain = Blockchain::new();
+    new_blockchain.add_block(vec![included]);
+    new_blockchain.add_block(vec![]);
+
+    let disconnected = old_blockchain.disconnected_by(&new_blockchain.chain);
+    assert_eq!(disconnected, old_blockchain.chain[1..]);
+    let mut transaction_pool = TransactionPool::new();
+    transaction_pool.readd_disconnected(&disconnected, &new_blockchain);
+    assert_eq!(transaction_pool.transactions(), vec![abandoned]);
+}
+
+#[test]
+fn test_rejects_a_transaction_without_input() {
+    let mut transaction_pool = TransactionPool::new();
+    let transaction = Transaction { id: TransactionId::default(), version: TRANSACTION_VERSION, input: None, outputs: vec![] };
+    assert_eq!(transaction_pool.update_or_add_transaction(transaction, &Blockchain::new()), Err(PoolError::Invalid { reason: TxError::MissingInput }));
+    assert!(transaction_pool.is_empty());
+}
+
+#[test]
+fn test_rejects_a_transaction_with_an_invalid_signature() {
+    let mut transaction_pool = TransactionPool::new();
+    let mut transaction = Transaction::new(&Wallet::new(), Wallet::new().public_key, 50).unwrap();
+    transaction.outputs[1].address = "attacker".to_string();
+    transaction.id = transaction.compute_id();
+    assert_eq!(transaction_pool.update_or_add_transaction(transaction, &Blockchain::new()), Err(PoolError::Invalid { reason: TxError::SignatureMismatch }));
+}
+
+#[test]
+fn test_rejects_a_transaction_for_another_chain() {
+    let mut transaction_pool = TransactionPool::new();
+    let transaction = Transaction::new(&Wallet::new(), Wallet::new().public_key, 50).unwrap();
+    let blockchain = Blockchain::with_chain_id(2);
+    assert_eq!(transaction_pool.update_or_add_transaction(transaction, &blockchain), Err(PoolError::WrongChain { expected: 2, found: 1 }));
+}
+
+#[test]
+fn test_rejects_a_transaction_spending_more_than_the_chain_balance() {
+    let mut transaction_pool = TransactionPool::new();
+    let mut wallet = Wallet::new();
+    wallet.balance = 1000;
+    let transaction = Transaction::new(&wallet, Wallet::new().public_key, 50).unwrap();
+    assert_eq!(transaction_pool.update_or_add_transaction(transaction, &Blockchain::new()), Err(PoolError::InsufficientBalance { balance: 500, amount: 1000 }));
+}
 
 #[test]
-fn test_update_transaction_in_the_pool() {
+fn test_rejects_a_replacement_without_a_higher_fee() {
     let mut transaction_pool = TransactionPool::new();
     let wallet = Wallet::new();
-    let mut transaction = Transaction::new(&wallet, "recipient".to_string(), 50);
-    transaction_pool.update_or_add_transaction(transaction.clone());
-    transaction.update(&wallet, "new_recipient".to_string(), 25).expect("Failed to update transaction");
-    transaction_pool.update_or_add_transaction(transaction.clone());
-    assert_eq!(transaction_pool.transactions.len(), 1);
-    assert_eq!(transaction_pool.transactions[0], transaction);
+    let transaction = Transaction::new(&wallet, Wallet::new().public_key, 50).unwrap();
+    transaction_pool.update_or_add_transaction(transaction.clone(), &Blockchain::new()).unwrap();
+    let conflicting = Transaction::new(&wallet, Wallet::new().public_key, 60).unwrap();
+    assert_eq!(transaction_pool.update_or_add_transaction(conflicting, &Blockchain::new()),
+               Err(PoolError::ReplacementFeeTooLow { pending: transaction.id, fee: 0, minimum: 1 }));
+    assert_eq!(transaction_pool.transactions(), vec![transaction]);
 }
 
+#[test]
+fn test_rejects_a_replacement_spending_another_input() {
+    let mut transaction_pool = TransactionPool::new();
+    let mut wallet = Wallet::new();
+    let transaction = Transaction::new(&wallet, Wallet::new().public_key, 50).unwrap();
+    transaction_pool.update_or_add_transaction(transaction.clone(), &Blockchain::new()).unwrap();
+    wallet.balance = 400;
+    let outputs = vec![TransactionOutput::new(345, wallet.public_key.clone()), TransactionOutput::new(50, Wallet::new().public_key)];
+    let replacement = Transaction::transaction_with_outputs(&wallet, outputs);
+    assert_eq!(transaction_pool.update_or_add_transaction(replacement, &Blockchain::new()),
+               Err(PoolError::ReplacementInputMismatch { pending: transaction.id }));
+}
+
+#[test]
+fn test_evicts_the_lowest_fee_rate_transaction_when_full() {
+    let mut transaction_pool = TransactionPool::with_limits(limits(2));
+    let (low, high, medium) = (transaction_with_fee(1), transaction_with_fee(5), transaction_with_fee(3));
+    transaction_pool.update_or_add_transaction(low.clone(), &Blockchain::new()).unwrap();
+    transaction_pool.update_or_add_transaction(high.clone(), &Blockchain::new()).unwrap();
+    transaction_pool.update_or_add_transaction(medium.clone(), &Blockchain::new()).unwrap();
+    assert_eq!(transaction_pool.len(), 2);
+    assert!(transaction_pool.get(&low.id).is_none());
+    assert!(transaction_pool.get(&high.id).is_some());
+    assert!(transaction_pool.get(&medium.id).is_some());
+}
+
+#[test]
+fn test_rejects_a_transaction_paying_less_than_a_full_pool() {
+    let mut transaction_pool = TransactionPool::with_limits(limits(1));
+    transaction_pool.update_or_add_transaction(transaction_with_fee(5), &Blockchain::new()).unwrap();
+    assert_eq!(transaction_pool.update_or_add_transaction(transaction_with_fee(1), &Blockchain::new()), Err(PoolError::PoolFull));
+    assert_eq!(transaction_pool.len(), 1);
+}
+
+#[test]
+fn test_rejects_a_transaction_larger_than_the_pool() {
+    let mut transaction_pool = TransactionPool::with_limits(PoolLimits { max_bytes: 100, ..PoolLimits::default() });
+    assert_eq!(transaction_pool.update_or_add_transaction(transaction_with_fee(5), &Blockchain::new()), Err(PoolError::PoolFull));
+    assert_eq!(transaction_pool.size_in_bytes(), 0);
+}
+
+#[test]
+fn test_expires_stale_transactions() {
+    let mut transaction_pool = TransactionPool::with_limits(PoolLimits { expiry: TimeDelta::seconds(60), ..PoolLimits::default() });
+    let transaction = transaction_with_fee(0);
+    transaction_pool.update_or_add_transaction(transaction.clone(), &Blockchain::new()).unwrap();
+    assert!(transaction_pool.expire(Utc::now()).is_empty());
+    assert_eq!(transaction_pool.expire(Utc::now() + TimeDelta::seconds(61)), vec![transaction.id]);
+    assert!(transaction_pool.is_empty());
+    assert!(transaction_pool.existing_transaction(&transaction.input.unwrap().address).is_none());
+}
+
+#[test]
+fn test_rejects_a_transaction_with_a_used_nonce() {
+    let mut transaction_pool = TransactionPool::new();
+    let wallet = Wallet::new();
+    let mut blockchain = Blockchain::new();
+    blockchain.add_block(vec![Transaction::new(&wallet, Wallet::new().public_key, 50).unwrap()]);
+    let replayed = Transaction::new(&wallet, Wallet::new().public_key, 10).unwrap();
+    assert_eq!(transaction_pool.update_or_add_transaction(replayed, &blockchain), Err(PoolError::StaleNonce { last: 1, nonce: 1 }));
+}
+
+#[test]
+fn test_rejects_a_relabelled_transaction() {
+    let mut transaction_pool = TransactionPool::new();
+    let mut transaction = Transaction::new(&Wallet::new(), Wallet::new().public_key, 50).unwrap();
+    let expected = transaction.id;
+    transaction.id = TransactionId([7; 32]);
+    assert_eq!(transaction_pool.update_or_add_transaction(transaction, &Blockchain::new()), Err(PoolError::IdMismatch { expected }));
+}
+
+#[test]
+fn test_rejects_a_legacy_transaction() {
+    let mut transaction_pool = TransactionPool::new();
+    let mut transaction = Transaction::new(&Wallet::new(), Wallet::new().public_key, 50).unwrap();
+    transaction.version = LEGACY_TRANSACTION_VERSION;
+    assert_eq!(transaction_pool.update_or_add_transaction(transaction, &Blockchain::new()),
+               Err(PoolError::UnsupportedVersion { version: LEGACY_TRANSACTION_VERSION, minimum: TRANSACTION_VERSION }));
+}
+
+#[test]
+fn test_builds_unsigned_transactions_for_any_sender() {
+    let mut transaction_pool = TransactionPool::new();
+    let blockchain = Blockchain::new();
+    let wallet = Wallet::new();
+    let unsigned = UnsignedTransaction::build(&wallet.public_key, blockchain.chain_id, Wallet::new().public_key, 50, &transaction_pool, &blockchain).unwrap();
+    transaction_pool.update_or_add_transaction(unsigned.sign(&wallet).unwrap(), &blockchain).unwrap();
+
+    // a second payment replaces the pending one and outbids it
+    let replacement = UnsignedTransaction::build(&wallet.public_key, blockchain.chain_id, Wallet::new().public_key, 20, &transaction_pool, &blockchain).unwrap();
+    assert_eq!((replacement.nonce, replacement.fee()), (1, 1));
+    transaction_pool.update_or_add_transaction(replacement.sign(&wallet).unwrap(), &blockchain).unwrap();
+    assert_eq!(transaction_pool.len(), 1);
+
+    let result = UnsignedTransaction::build(&wallet.public_key, blockchain.chain_id, Wallet::new().public_key, 100000, &transaction_pool, &blockchain);
+    assert_eq!(result, Err(PoolError::InsufficientBalance { balance: wallet.balance, amount: 100000 }));
+}
+
+#[test]
+fn test_validates_a_follow_up_to_a_transaction_without_change() {
+    let mut transaction_pool = TransactionPool::new();
+    let mut blockchain = Blockchain::new();
+    let mut wallet = Wallet::new();
+    let spend_all = Transaction::transaction_with_outputs(&wallet, vec![TransactionOutput::new(wallet.balance, Wallet::new().public_key)]);
+    transaction_pool.update_or_add_transaction(spend_all.clone(), &blockchain).unwrap();
+    blockchain.add_block(vec![spend_all]);
+    transaction_pool.remove_confirmed(&blockchain.chain);
+
+    wallet.nonce = 2;
+    let follow_up = Transaction::new(&wallet, Wallet::new().public_key, 50).unwrap();
+    assert_eq!(transaction_pool.update_or_add_transaction(follow_up, &blockchain),
+               Err(PoolError::InsufficientBalance { balance: 0, amount: wallet.balance }));
+}
+
+#[test]
+fn test_rejects_a_transaction_paying_to_something_other_than_a_public_key() {
+    let mut transaction_pool = TransactionPool::new();
+    let transaction = Transaction::new(&Wallet::new(), "typo".to_string(), 50).unwrap();
+    assert_eq!(transaction_pool.update_or_add_transaction(transaction, &Blockchain::new()),
+               Err(PoolError::InvalidRecipient { address: "typo".to_string() }));
+    assert!(transaction_pool.is_empty());
+}
@@ -1,5 +1,6 @@
 use crate::utils::config::MINING_REWARD;
-use crate::wallet::transaction::Transaction;
+use chrono::{TimeDelta, Utc};
+use crate::wallet::transaction::{Transaction, TransactionId, TransactionOutput, TxError, UnsignedTransaction, LEGACY_TRANSACTION_VERSION, TRANSACTION_VERSION};
 use crate::wallet::wallet::Wallet;
 
 #[test]
@@ -7,7 +8,7 @@ fn test_outputs_the_amount_subtracted_from_the_sender_balance() {
     let sender_wallet = Wallet::new();
     let recipient = "recipient".to_string();
     let amount = 50;
-    let transaction = Transaction::new(&sender_wallet, recipient.clone(), amount);
+    let transaction = Transaction::new(&sender_wallet, recipient.clone(), amount).unwrap();
     assert_eq!(transaction.outputs[0].amount, sender_wallet.balance - amount);
 }
 
@@ -16,7 +17,7 @@ fn test_outputs_the_amount_added_to_the_recipient_balance() {
     let sender_wallet = Wallet::new();
     let recipient = "recipient".to_string();
     let amount = 50;
-    let transaction = Transaction::new(&sender_wallet, recipient.clone(), amount);
+    let transaction = Transaction::new(&sender_wallet, recipient.clone(), amount).unwrap();
     assert_eq!(transaction.outputs[1].amount, amount);
 }
 
@@ -25,7 +26,7 @@ fn test_transacting_with_amount_exceeding_balance() {
     let sender_wallet = Wallet::new();
     let recipient = "recipient".to_string();
     let amount = 100000;
-    assert!(Transaction::new(&sender_wallet, recipient.clone(), amount).outputs.is_empty());
+    assert_eq!(Transaction::new(&sender_wallet, recipient.clone(), amount), Err(TxError::InsufficientFunds { balance: sender_wallet.balance, amount }));
 }
 
 #[test]
@@ -33,7 +34,7 @@ fn test_inputs_the_balance_of_the_wallet() {
     let sender_wallet = Wallet::new();
     let recipient = "recipient".to_string();
     let amount = 50;
-    let transaction = Transaction::new(&sender_wallet, recipient.clone(), amount);
+    let transaction = Transaction::new(&sender_wallet, recipient.clone(), amount).unwrap();
     assert_eq!(transaction.input.as_ref().unwrap().amount, sender_wallet.balance);
 }
 
@@ -43,9 +44,9 @@ fn test_validates_a_valid_transaction() {
     let sender_wallet = Wallet::new();
     let recipient = "recipient".to_string();
     let amount = 50;
-    let transaction = Transaction::new(&sender_wallet, recipient.clone(), amount);
+    let transaction = Transaction::new(&sender_wallet, recipient.clone(), amount).unwrap();
 
-    assert!(transaction.verify());
+    assert_eq!(transaction.verify(), Ok(()));
 }
 
 
@@ -54,9 +55,9 @@ fn test_invalidates_a_corrupt_transaction() {
     let sender_wallet = Wallet::new();
     let recipient = "recipient".to_string();
     let amount = 50;
-    let mut transaction = Transaction::new(&sender_wallet, recipient.clone(), amount);
+    let mut transaction = Transaction::new(&sender_wallet, recipient.clone(), amount).unwrap();
     transaction.outputs[0].amount = 100000;
-    assert!(!transaction.verify());
+    assert_eq!(transaction.verify(), Err(TxError::SignatureMismatch));
 }
 
 // test update transaction
@@ -65,7 +66,7 @@ fn test_updates_the_transaction() {
     let sender_wallet = Wallet::new();
     let recipient = "recipient".to_string();
     let amount = 50;
-    let mut transaction = Transaction::new(&sender_wallet, recipient.clone(), amount);
+    let mut transaction = Transaction::new(&sender_wallet, recipient.clone(), amount).unwrap();
     let new_amount = 25;
     let new_recipient = "new_recipient".to_string();
     transaction.update(&sender_wallet, new_recipient.clone(), new_amount).expect("Could not update transaction");
@@ -83,4 +84,158 @@ fn test_reward_transaction() {
     assert_eq!(transaction.outputs.len(), 1);
     assert_eq!(transaction.outputs[0].amount, MINING_REWARD);
     assert_eq!(transaction.outputs[0].address, miner_wallet.public_key);
-}
\ No newline at end of file
+}
+
+#[test]
+fn test_invalidates_a_transaction_replayed_on_another_chain() {
+    let sender_wallet = Wallet::new();
+    let mut transaction = Transaction::new(&sender_wallet, "recipient".to_string(), 50).unwrap();
+    transaction.input.as_mut().unwrap().chain_id = sender_wallet.chain_id + 1;
+    assert_eq!(transaction.verify(), Err(TxError::SignatureMismatch));
+}
+
+#[test]
+fn test_reward_transaction_collects_fees() {
+    let miner_wallet = Wallet::new();
+    let transaction = Transaction::reward_transaction_with_fees(&miner_wallet, &Wallet::blockchain_wallet(), 7);
+    assert_eq!(transaction.outputs[0].amount, MINING_REWARD + 7);
+}
+
+#[test]
+fn test_fee_is_the_unspent_part_of_the_input() {
+    let sender_wallet = Wallet::new();
+    let mut transaction = Transaction::new(&sender_wallet, "recipient".to_string(), 50).unwrap();
+    assert_eq!(transaction.fee(), 0);
+    transaction.outputs[0].amount -= 3;
+    assert_eq!(transaction.fee(), 3);
+}
+
+#[test]
+fn test_id_is_derived_from_the_signed_contents() {
+    let sender_wallet = Wallet::new();
+    let mut transaction = Transaction::new(&sender_wallet, "recipient".to_string(), 50).unwrap();
+    assert!(transaction.has_valid_id());
+    transaction.outputs[1].amount = 40;
+    assert!(!transaction.has_valid_id());
+}
+
+#[test]
+fn test_id_serializes_as_hex() {
+    let transaction = Transaction::new(&Wallet::new(), "recipient".to_string(), 50).unwrap();
+    let json = serde_json::to_value(transaction.id).unwrap();
+    assert_eq!(json, serde_json::json!(hex::encode(transaction.id.0)));
+    assert_eq!(serde_json::from_value::<TransactionId>(json).unwrap(), transaction.id);
+    assert!("not hex".parse::<TransactionId>().is_err());
+}
+
+fn assert_tampering_is_detected(tamper: impl Fn(&mut Transaction)) {
+    let mut transaction = Transaction::new(&Wallet::new(), "recipient".to_string(), 50).unwrap();
+    tamper(&mut transaction);
+    assert!(transaction.verify().is_err());
+}
+
+#[test]
+fn test_detects_tampering_with_the_input() {
+    assert_tampering_is_detected(|transaction| transaction.input.as_mut().unwrap().amount = 100000);
+    assert_tampering_is_detected(|transaction| transaction.input.as_mut().unwrap().timestamp = Utc::now() + TimeDelta::days(1));
+    assert_tampering_is_detected(|transaction| transaction.input.as_mut().unwrap().address = Wallet::new().public_key);
+    assert_tampering_is_detected(|transaction| transaction.input.as_mut().unwrap().nonce += 1);
+    assert_tampering_is_detected(|transaction| transaction.input.as_mut().unwrap().chain_id += 1);
+}
+
+#[test]
+fn test_detects_tampering_with_the_version() {
+    assert_tampering_is_detected(|transaction| transaction.version = LEGACY_TRANSACTION_VERSION);
+    assert_tampering_is_detected(|transaction| transaction.version = TRANSACTION_VERSION + 1);
+}
+
+#[test]
+fn test_verifies_legacy_transactions() {
+    let sender_wallet = Wallet::new();
+    let mut transaction = Transaction::new(&sender_wallet, "recipient".to_string(), 50).unwrap();
+    transaction.version = LEGACY_TRANSACTION_VERSION;
+    let sighash = transaction.sighash().unwrap();
+    transaction.input.as_mut().unwrap().signature = sender_wallet.sign(&sighash);
+    assert_eq!(transaction.verify(), Ok(()));
+    // which is why neither the pool nor a valid chain accepts them: the input amount is not signed
+    transaction.input.as_mut().unwrap().amount = 100000;
+    assert_eq!(transaction.verify(), Ok(()));
+}
+
+#[test]
+fn test_reads_transactions_without_a_version_as_legacy() {
+    let transaction = Transaction::new(&Wallet::new(), "recipient".to_string(), 50).unwrap();
+    let mut json = serde_json::to_value(&transaction).unwrap();
+    json.as_object_mut().unwrap().remove("version");
+    assert_eq!(serde_json::from_value::<Transaction>(json).unwrap().version, LEGACY_TRANSACTION_VERSION);
+}
+
+#[test]
+fn test_reports_malformed_encodings_instead_of_panicking() {
+    let mut transaction = Transaction::new(&Wallet::new(), "recipient".to_string(), 50).unwrap();
+    transaction.input.as_mut().unwrap().signature = "not hex".to_string();
+    assert_eq!(transaction.verify(), Err(TxError::InvalidSignatureEncoding));
+    transaction.input.as_mut().unwrap().signature = "00ff".to_string();
+    assert_eq!(transaction.verify(), Err(TxError::InvalidSignatureEncoding));
+    transaction.input.as_mut().unwrap().address = "not hex".to_string();
+    assert_eq!(transaction.verify(), Err(TxError::InvalidAddress));
+    transaction.input.as_mut().unwrap().address = "00ff".to_string();
+    assert_eq!(transaction.verify(), Err(TxError::InvalidAddress));
+}
+
+#[test]
+fn test_reports_a_missing_input() {
+    let transaction = Transaction { id: TransactionId::default(), version: TRANSACTION_VERSION, input: None, outputs: vec![] };
+    assert_eq!(transaction.verify(), Err(TxError::MissingInput));
+}
+
+#[test]
+fn test_reports_outputs_exceeding_the_input() {
+    let wallet = Wallet::new();
+    let outputs = vec![
+        TransactionOutput::new(wallet.balance, wallet.public_key.clone()),
+        TransactionOutput::new(50, "recipient".to_string()),
+    ];
+    let transaction = Transaction::transaction_with_outputs(&wallet, outputs);
+    assert_eq!(transaction.verify(), Err(TxError::AmountMismatch { input: wallet.balance, outputs: wallet.balance + 50 }));
+}
+
+#[test]
+fn test_rejects_zero_amounts_and_self_payments() {
+    let sender_wallet = Wallet::new();
+    assert_eq!(Transaction::new(&sender_wallet, "recipient".to_string(), 0), Err(TxError::ZeroAmount));
+    assert_eq!(Transaction::new(&sender_wallet, sender_wallet.public_key.clone(), 50), Err(TxError::SelfPayment));
+}
+
+#[test]
+fn test_update_reports_which_condition_failed() {
+    let sender_wallet = Wallet::new();
+    let mut transaction = Transaction::new(&sender_wallet, "recipient".to_string(), 400).unwrap();
+    let original = transaction.clone();
+    assert_eq!(transaction.update(&sender_wallet, "recipient".to_string(), 200), Err(TxError::InsufficientFunds { balance: 100, amount: 200 }));
+    assert_eq!(transaction.update(&sender_wallet, "recipient".to_string(), 0), Err(TxError::ZeroAmount));
+    assert_eq!(transaction.update(&Wallet::new(), "recipient".to_string(), 50), Err(TxError::MissingSenderOutput));
+    assert_eq!(transaction, original);
+}
+
+#[test]
+fn test_replace_reports_overflow() {
+    let sender_wallet = Wallet::new();
+    let transaction = Transaction::new(&sender_wallet, "recipient".to_string(), 50).unwrap();
+    assert_eq!(transaction.replace(&sender_wallet, "recipient".to_string(), u64::MAX, 1), Err(TxError::Overflow));
+}
+
+#[test]
+fn test_signs_an_unsigned_transaction_with_the_sender_key_only() {
+    let sender_wallet = Wallet::new();
+    let unsigned = UnsignedTransaction::payment(&sender_wallet.public_key, 500, sender_wallet.chain_id, 1, "recipient".to_string(), 50).unwrap();
+    // survives the trip to the signing machine and back
+    let unsigned: UnsignedTransaction = serde_json::from_str(&serde_json::to_string(&unsigned).unwrap()).unwrap();
+    assert_eq!(unsigned.clone().sign(&Wallet::new()), Err(TxError::WrongSigner));
+
+    let transaction = unsigned.clone().sign(&sender_wallet).unwrap();
+    assert_eq!(transaction.verify(), Ok(()));
+    assert!(transaction.has_valid_id());
+    assert_eq!(transaction.outputs, unsigned.outputs);
+    assert_eq!(transaction.nonce(), Some(1));
+}
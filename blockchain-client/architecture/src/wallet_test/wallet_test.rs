@@ -1,6 +1,9 @@
+use chrono::{TimeDelta, Utc};
 use crate::blockchain::blockchain::Blockchain;
-use crate::wallet::transaction_pool::TransactionPool;
-use crate::wallet::wallet::Wallet;
+use crate::utils::config::{INITIAL_BALANCE, MINING_REWARD};
+use crate::wallet::transaction::{Transaction, TxError, UnsignedTransaction};
+use crate::wallet::transaction_pool::{PoolError, TransactionPool};
+use crate::wallet::wallet::{BalanceReport, Wallet};
 
 #[test]
 fn test_print_wallet() {
@@ -13,11 +16,12 @@ fn test_doubles_the_send_amount_subtracted_from_the_wallet_balance() {
     let mut sender_wallet = Wallet::new();
     let mut tp = TransactionPool::new();
     let sender_amount = 50;
-    let recipient = "recipient".to_string();
+    let recipient = Wallet::new().public_key;
     let blockchain = Blockchain::new();
-    let transaction = sender_wallet.create_transaction(recipient.clone(), sender_amount, &mut tp, &blockchain).unwrap();
+    let _transaction = sender_wallet.create_transaction(recipient.clone(), sender_amount, &mut tp, &blockchain).unwrap();
     let transaction2 = sender_wallet.create_transaction(recipient.clone(), sender_amount, &mut tp, &blockchain).unwrap();
-    assert_eq!(transaction2.outputs[0].amount, sender_wallet.balance - sender_amount * 2);
+    assert_eq!(transaction2.outputs[0].amount, sender_wallet.balance - sender_amount * 2 - transaction2.fee());
+    assert_eq!(transaction2.fee(), 1);
 }
 
 
@@ -26,9 +30,96 @@ fn clones_the_send_amount_output_for_the_recipient() {
     let mut sender_wallet = Wallet::new();
     let mut tp = TransactionPool::new();
     let sender_amount = 50;
-    let recipient = "recipient".to_string();
+    let recipient = Wallet::new().public_key;
     let blockchain = Blockchain::new();
     sender_wallet.create_transaction(recipient.clone(), sender_amount, &mut tp, &blockchain).unwrap();
     let transaction2 = sender_wallet.create_transaction(recipient.clone(), sender_amount, &mut tp, &blockchain).unwrap();
     assert_eq!(transaction2.outputs.iter().filter(|output| output.address == recipient).map(|output| output.amount).collect::<Vec<u64>>(), vec![sender_amount, sender_amount]);
-}
\ No newline at end of file
+}
+
+#[test]
+fn test_tracks_the_next_nonce_across_pending_and_confirmed_transactions() {
+    let mut sender_wallet = Wallet::new();
+    let mut tp = TransactionPool::new();
+    let mut blockchain = Blockchain::new();
+    let transaction = sender_wallet.create_transaction(Wallet::new().public_key, 50, &mut tp, &blockchain).unwrap();
+    assert_eq!(transaction.nonce(), Some(1));
+    assert_eq!(sender_wallet.next_nonce(&tp, &blockchain), 2);
+    // replacing the pending transaction keeps its nonce
+    let replacement = sender_wallet.create_transaction(Wallet::new().public_key, 50, &mut tp, &blockchain).unwrap();
+    assert_eq!(replacement.nonce(), Some(1));
+
+    blockchain.add_block(vec![replacement]);
+    tp.remove_confirmed(&blockchain.chain);
+    let transaction = sender_wallet.create_transaction(Wallet::new().public_key, 50, &mut tp, &blockchain).unwrap();
+    assert_eq!(transaction.nonce(), Some(2));
+}
+
+#[test]
+fn test_does_not_pool_a_zero_amount_transaction() {
+    let mut sender_wallet = Wallet::new();
+    let mut tp = TransactionPool::new();
+    let result = sender_wallet.create_transaction(Wallet::new().public_key, 0, &mut tp, &Blockchain::new());
+    assert_eq!(result, Err(PoolError::Invalid { reason: TxError::ZeroAmount }));
+    assert!(tp.is_empty());
+}
+
+#[test]
+fn test_reports_pending_amounts_apart_from_the_confirmed_balance() {
+    let (mut sender_wallet, recipient_wallet) = (Wallet::new(), Wallet::new());
+    let mut tp = TransactionPool::new();
+    let blockchain = Blockchain::new();
+    let transaction = sender_wallet.create_transaction(recipient_wallet.public_key.clone(), 50, &mut tp, &blockchain).unwrap();
+    assert_eq!(transaction.fee(), 0);
+
+    assert_eq!(Wallet::balance_report(&sender_wallet.public_key, &blockchain, &tp, 1), BalanceReport {
+        confirmed: INITIAL_BALANCE,
+        spendable: INITIAL_BALANCE - 50,
+        pending_incoming: 0,
+        pending_outgoing: 50,
+    });
+    assert_eq!(Wallet::balance_report(&recipient_wallet.public_key, &blockchain, &tp, 1), BalanceReport {
+        confirmed: INITIAL_BALANCE,
+        spendable: INITIAL_BALANCE,
+        pending_incoming: 50,
+        pending_outgoing: 0,
+    });
+}
+
+#[test]
+fn test_funds_become_spendable_after_enough_confirmations() {
+    let miner_wallet = Wallet::new();
+    let tp = TransactionPool::new();
+    let mut blockchain = Blockchain::new();
+    blockchain.add_block(vec![Transaction::reward_transaction(&miner_wallet, &Wallet::blockchain_wallet())]);
+
+    let report = Wallet::balance_report(&miner_wallet.public_key, &blockchain, &tp, 2);
+    assert_eq!((report.confirmed, report.spendable), (INITIAL_BALANCE + MINING_REWARD, INITIAL_BALANCE));
+    assert_eq!(Wallet::balance_report(&miner_wallet.public_key, &blockchain, &tp, 1).spendable, INITIAL_BALANCE + MINING_REWARD);
+
+    blockchain.add_block(vec![]);
+    assert_eq!(Wallet::balance_report(&miner_wallet.public_key, &blockchain, &tp, 2).spendable, INITIAL_BALANCE + MINING_REWARD);
+}
+
+fn payment_at(sender: &Wallet, balance: u64, recipient: &Wallet, amount: u64, seconds_ago: i64) -> Transaction {
+    let mut unsigned = UnsignedTransaction::payment(&sender.public_key, balance, sender.chain_id, 1, recipient.public_key.clone(), amount).unwrap();
+    unsigned.timestamp = Utc::now() - TimeDelta::seconds(seconds_ago);
+    unsigned.sign(sender).unwrap()
+}
+
+#[test]
+fn test_funds_received_after_an_immature_spend_are_not_spendable() {
+    let wallet = Wallet::new();
+    let tp = TransactionPool::new();
+    let mut blockchain = Blockchain::new();
+    blockchain.add_block(vec![payment_at(&Wallet::new(), INITIAL_BALANCE, &wallet, 100, 30)]);
+    // the tip spends all but 10 of the mature funds, then receives more
+    blockchain.add_block(vec![
+        payment_at(&wallet, INITIAL_BALANCE + 100, &Wallet::new(), 590, 20),
+        payment_at(&Wallet::new(), INITIAL_BALANCE, &wallet, 200, 10),
+    ]);
+
+    let report = Wallet::balance_report(&wallet.public_key, &blockchain, &tp, 2);
+    assert_eq!((report.confirmed, report.spendable), (210, 10));
+    assert_eq!(Wallet::balance_report(&wallet.public_key, &blockchain, &tp, 1).spendable, 210);
+}
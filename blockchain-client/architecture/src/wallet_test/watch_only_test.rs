@@ -0,0 +1,47 @@
+use crate::blockchain::blockchain::Blockchain;
+use crate::utils::config::{INITIAL_BALANCE, MINING_REWARD};
+use crate::wallet::transaction::Transaction;
+use crate::wallet::transaction_pool::TransactionPool;
+use crate::wallet::wallet::Wallet;
+use crate::wallet::watch_only::WatchOnlyWallet;
+
+#[test]
+fn test_rescans_balances_and_history_of_every_address() {
+    let (cold, other) = (Wallet::new(), Wallet::new());
+    let mut watch_only = WatchOnlyWallet::new(vec![cold.address(), other.address(), cold.address()]);
+    assert_eq!(watch_only.addresses().len(), 2);
+
+    let mut blockchain = Blockchain::new();
+    blockchain.add_block(vec![Transaction::reward_transaction(&cold, &Wallet::blockchain_wallet())]);
+    watch_only.sync(&blockchain);
+    assert_eq!(watch_only.balance_of(&cold.address()), INITIAL_BALANCE + MINING_REWARD);
+    assert_eq!(watch_only.balance_of(&other.address()), 0);
+    assert_eq!(watch_only.balance(), INITIAL_BALANCE + MINING_REWARD);
+    assert_eq!(watch_only.history().len(), 1);
+
+    blockchain.add_block(vec![Transaction::reward_transaction(&other, &Wallet::blockchain_wallet())]);
+    watch_only.sync(&blockchain);
+    assert_eq!(watch_only.balance(), 2 * INITIAL_BALANCE + 2 * MINING_REWARD);
+    assert_eq!(watch_only.history().len(), 2);
+}
+
+#[test]
+fn test_counts_only_what_others_pay_as_pending_incoming() {
+    let (cold, other, stranger) = (Wallet::new(), Wallet::new(), Wallet::new());
+    let watch_only = WatchOnlyWallet::new(vec![cold.address(), other.address()]);
+    let blockchain = Blockchain::new();
+    let mut transaction_pool = TransactionPool::new();
+    transaction_pool.update_or_add_transaction(Transaction::new(&stranger, cold.public_key.clone(), 30).unwrap(), &blockchain).unwrap();
+    // a transfer between watched addresses does not add to the wallet
+    transaction_pool.update_or_add_transaction(Transaction::new(&cold, other.public_key.clone(), 40).unwrap(), &blockchain).unwrap();
+
+    assert_eq!(watch_only.pending(&transaction_pool).len(), 2);
+    assert_eq!(watch_only.pending_incoming(&transaction_pool), 30);
+}
+
+#[test]
+fn test_fresh_addresses_hold_nothing() {
+    let mut watch_only = WatchOnlyWallet::new((0..10).map(|_| Wallet::new().address()).collect());
+    watch_only.sync(&Blockchain::new());
+    assert_eq!(watch_only.balance(), 0);
+}
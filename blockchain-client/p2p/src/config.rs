@@ -0,0 +1,65 @@
+use std::path::PathBuf;
+use std::time::Duration;
+use libp2p::Multiaddr;
+use architecture::blockchain::blockchain::{Blockchain, DEFAULT_CHAIN_ID};
+use architecture::wallet::transaction_pool::PoolLimits;
+
+pub(crate) const REDIAL_INTERVAL: Duration = Duration::from_secs(10);
+pub(crate) const DISCOVERY_INTERVAL: Duration = Duration::from_secs(30);
+pub(crate) const MEMPOOL_EXPIRY_INTERVAL: Duration = Duration::from_secs(60);
+pub(crate) const MEMPOOL_SAVE_INTERVAL: Duration = Duration::from_secs(60);
+pub(crate) const NODE_KEY_FILE: &str = "node_key";
+pub(crate) const BANS_FILE: &str = "bans.json";
+pub(crate) const MEMPOOL_FILE: &str = "mempool.json";
+pub(crate) const WALLET_FILE: &str = "wallet.json";
+pub(crate) const WALLETS_DIR: &str = "wallets";
+
+/// Funds count as spendable once the block paying them is the tip, raise it to wait for blocks on top.
+pub const DEFAULT_CONFIRMATIONS: usize = 1;
+pub(crate) const DEFAULT_BAN_DURATION_SECS: u64 = 24 * 60 * 60;
+pub(crate) const MAX_CONNECTIONS_PER_PEER: u32 = 2;
+pub(crate) const VALID_MESSAGE_SCORE: i64 = 1;
+pub(crate) const INVALID_MESSAGE_PENALTY: i64 = -10;
+
+#[derive(Clone, Debug)]
+pub struct NodeConfig {
+    pub http_port: String,
+    pub listen_addresses: Vec<Multiaddr>,
+    pub bootstrap_peers: Vec<Multiaddr>,
+    pub data_dir: PathBuf,
+    pub node_key_file: Option<PathBuf>,
+    pub wallet_file: Option<PathBuf>,
+    pub chain_id: u32,
+    pub mempool_limits: PoolLimits,
+    /// Confirmations funds need before the node's wallets can spend them.
+    pub confirmations: usize,
+}
+
+impl NodeConfig {
+    pub fn new(http_port: String) -> NodeConfig {
+        let data_dir = PathBuf::from("data").join(format!("node-{http_port}"));
+        NodeConfig {
+            http_port,
+            listen_addresses: vec![
+                "/ip4/0.0.0.0/udp/0/quic-v1".parse().unwrap(),
+                "/ip4/0.0.0.0/tcp/0".parse().unwrap(),
+            ],
+            bootstrap_peers: vec![],
+            data_dir,
+            node_key_file: None,
+            wallet_file: None,
+            chain_id: DEFAULT_CHAIN_ID,
+            mempool_limits: PoolLimits::default(),
+            confirmations: DEFAULT_CONFIRMATIONS,
+        }
+    }
+
+    /// The wallet keystore, kept in the data directory unless configured otherwise.
+    pub fn wallet_file(&self) -> PathBuf {
+        self.wallet_file.clone().unwrap_or_else(|| self.data_dir.join(WALLET_FILE))
+    }
+
+    pub fn network_id(&self) -> String {
+        Blockchain::network_id(self.chain_id)
+    }
+}
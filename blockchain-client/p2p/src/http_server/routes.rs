@@ -1,11 +1,20 @@
-use std::ops::DerefMut;
 use std::sync::Arc;
 
-use tokio::sync::Mutex;
+use tokio::sync::{oneshot, Mutex};
 use warp::http::StatusCode;
+use warp::hyper::body::Bytes;
 
-use crate::Node;
-use crate::types::dto::{Balance, PublicKey, TransactionData};
+use architecture::wallet::keystore::KeystoreError;
+use architecture::wallet::address::{Address, AddressError};
+use architecture::wallet::transaction::Transaction;
+use architecture::wallet::transaction_pool::PoolError;
+use architecture::wallet::wallet::{BalanceReport, Wallet};
+use architecture::wallet::watch_only::WatchOnlyWallet;
+use crate::{Command, Multiaddr, Node, PeerId, WalletError};
+use crate::config::DEFAULT_BAN_DURATION_SECS;
+use crate::types::dto::{Balance, BanRequest, CreateWallet, ErrorMessage, ImportWatchOnly, LoadWallet, NodeStatus, PeerAddress, PublicKey,
+                        RejectedAddress, RejectedTransaction, RejectedWalletRequest, SubmittedTransaction, TransactionData, UnsignedTransactionData,
+                        WalletInfo, WalletTransactions, WatchOnlyBalance, WatchOnlyInfo};
 
 pub async fn hello_world() -> Result<impl warp::Reply, warp::Rejection> {
     Ok(warp::reply::json(&"Hello, world!"))
@@ -26,40 +35,320 @@ pub async fn mine_block(node: Arc<Mutex<Node>>) -> Result<impl warp::Reply, warp
 
 pub async fn print_transactions(node: Arc<Mutex<Node>>) -> Result<impl warp::Reply, warp::Rejection> {
     let node = node.lock().await;
-    let transactions = &node.transaction_pool.read().await.transactions;
+    let transactions = node.transaction_pool.read().await.transactions();
     Ok(warp::reply::with_status(warp::reply::json(&transactions), StatusCode::OK))
 }
 
 pub async fn post_transaction(node: Arc<Mutex<Node>>, data: TransactionData) -> Result<impl warp::Reply, warp::Rejection> {
     let node = node.lock().await;
-    let mut wallet = node.wallet.write().await.clone();
-    let blockchain = node.blockchain.read().await.clone();
-    let transaction = wallet.create_transaction(data.recipient, data.amount,
-                                                &mut node.transaction_pool.write().await.deref_mut(), &blockchain).unwrap();
+    let wallet = node.wallet.read().await.clone();
+    Ok(submit_transaction(&node, wallet, data).await)
+}
+
+async fn submit_transaction(node: &Node, wallet: Wallet, data: TransactionData) -> warp::reply::WithStatus<warp::reply::Json> {
+    // a mistyped recipient fails its checksum instead of receiving funds nobody can spend
+    let recipient = match Address::parse(&data.recipient, node.config.chain_id) {
+        Ok(recipient) => recipient,
+        Err(reason) => return address_error_reply(reason),
+    };
+    match node.submit_transaction_from(wallet, &recipient, data.amount).await {
+        Ok(transaction) => warp::reply::with_status(warp::reply::json(&transaction), StatusCode::CREATED),
+        Err(reason) => transaction_error_reply(reason),
+    }
+}
+
+fn address_error_reply(reason: AddressError) -> warp::reply::WithStatus<warp::reply::Json> {
+    let rejection = RejectedAddress { error: reason.to_string(), reason };
+    warp::reply::with_status(warp::reply::json(&rejection), StatusCode::BAD_REQUEST)
+}
+
+fn transaction_error_reply(reason: PoolError) -> warp::reply::WithStatus<warp::reply::Json> {
+    let rejection = RejectedTransaction { error: reason.to_string(), reason };
+    warp::reply::with_status(warp::reply::json(&rejection), StatusCode::BAD_REQUEST)
+}
+
+pub async fn post_unsigned_transaction(node: Arc<Mutex<Node>>, data: UnsignedTransactionData) -> Result<impl warp::Reply, warp::Rejection> {
+    let node = node.lock().await;
+    let (sender, recipient) = match (Address::parse(&data.sender, node.config.chain_id), Address::parse(&data.recipient, node.config.chain_id)) {
+        (Ok(sender), Ok(recipient)) => (sender, recipient),
+        (Err(reason), _) | (_, Err(reason)) => return Ok(address_error_reply(reason)),
+    };
+    match node.build_unsigned_transaction(&sender, &recipient, data.amount).await {
+        Ok(unsigned) => Ok(warp::reply::with_status(warp::reply::json(&unsigned), StatusCode::CREATED)),
+        Err(reason) => Ok(transaction_error_reply(reason)),
+    }
+}
 
-    let mut transaction_json = serde_json::to_string(&transaction).unwrap();
-    transaction_json = "transaction_pool: ".to_string() + &transaction_json;
-    node.event_sender.as_ref().unwrap().send(transaction_json).await
-        .expect("Failed to send message to event sender");
-    Ok(warp::reply::with_status(warp::reply::json(&transaction), StatusCode::CREATED))
+pub async fn post_raw_transaction(node: Arc<Mutex<Node>>, body: Bytes) -> Result<impl warp::Reply, warp::Rejection> {
+    // decoded here rather than by warp, so a malformed body gets the same structured rejection as an invalid transaction
+    let transaction = match serde_json::from_slice::<Transaction>(&body) {
+        Ok(transaction) => transaction,
+        Err(e) => return Ok(transaction_error_reply(PoolError::Malformed { message: e.to_string() })),
+    };
+    let node = node.lock().await;
+    match node.broadcast_transaction(transaction).await {
+        Ok(id) => Ok(warp::reply::with_status(warp::reply::json(&SubmittedTransaction { id }), StatusCode::ACCEPTED)),
+        Err(reason) => Ok(transaction_error_reply(reason)),
+    }
 }
 
 pub async fn get_public_key(node: Arc<Mutex<Node>>) -> Result<impl warp::Reply, warp::Rejection> {
     let node = node.lock().await;
     let wallet = node.wallet.read().await.clone();
     let pk = PublicKey {
-        public_key: wallet.public_key.clone()
+        public_key: wallet.public_key.clone(),
+        address: wallet.address(),
     };
     Ok(warp::reply::with_status(warp::reply::json(&pk), StatusCode::OK))
 }
 
 pub async fn get_wallet_balance(node: Arc<Mutex<Node>>) -> Result<impl warp::Reply, warp::Rejection> {
     let node = node.lock().await;
-    let wallet = node.wallet.read().await.clone();
-    let blockchain = node.blockchain.read().await.clone();
-    let balance = wallet.calculate_balance(&blockchain);
-    let balance = Balance {
-        balance
+    let balance = balance(node.wallet_balance().await, node.config.confirmations);
+    Ok(warp::reply::with_status(warp::reply::json(&balance), StatusCode::OK))
+}
+
+fn balance(report: BalanceReport, confirmations: usize) -> Balance {
+    Balance {
+        confirmed: report.confirmed,
+        spendable: report.spendable,
+        pending_incoming: report.pending_incoming,
+        pending_outgoing: report.pending_outgoing,
+        confirmations,
+    }
+}
+
+fn wallet_error_reply(reason: WalletError) -> warp::reply::WithStatus<warp::reply::Json> {
+    let status = match &reason {
+        WalletError::InvalidName { .. } | WalletError::NoAddresses | WalletError::InvalidAddress { .. } => StatusCode::BAD_REQUEST,
+        WalletError::AlreadyExists { .. } | WalletError::AlreadyLoaded { .. } => StatusCode::CONFLICT,
+        WalletError::NotFound { .. } | WalletError::NotLoaded { .. } => StatusCode::NOT_FOUND,
+        WalletError::WatchOnly { .. } => StatusCode::FORBIDDEN,
+        WalletError::Io { .. } => StatusCode::INTERNAL_SERVER_ERROR,
+        WalletError::Keystore { reason: KeystoreError::WrongPassword } => StatusCode::UNAUTHORIZED,
+        WalletError::Keystore { .. } => StatusCode::INTERNAL_SERVER_ERROR,
     };
+    let rejection = RejectedWalletRequest { error: reason.to_string(), reason };
+    warp::reply::with_status(warp::reply::json(&rejection), status)
+}
+
+fn wallet_info(name: &str, wallet: &Wallet) -> WalletInfo {
+    WalletInfo { name: name.to_string(), public_key: wallet.public_key.clone(), address: wallet.address() }
+}
+
+fn watch_only_info(name: &str, wallet: &WatchOnlyWallet) -> WatchOnlyInfo {
+    WatchOnlyInfo { name: name.to_string(), addresses: wallet.addresses().to_vec() }
+}
+
+pub async fn list_wallets(node: Arc<Mutex<Node>>) -> Result<impl warp::Reply, warp::Rejection> {
+    let node = node.lock().await;
+    let wallets = node.wallets.read().await.wallets().map(|(name, wallet)| wallet_info(name, wallet)).collect::<Vec<_>>();
+    Ok(warp::reply::with_status(warp::reply::json(&wallets), StatusCode::OK))
+}
+
+pub async fn create_wallet(node: Arc<Mutex<Node>>, data: CreateWallet) -> Result<impl warp::Reply, warp::Rejection> {
+    let node = node.lock().await;
+    let mut wallets = node.wallets.write().await;
+    match wallets.create(&data.name, &data.password) {
+        Ok(wallet) => Ok(warp::reply::with_status(warp::reply::json(&wallet_info(&data.name, wallet)), StatusCode::CREATED)),
+        Err(reason) => Ok(wallet_error_reply(reason)),
+    }
+}
+
+pub async fn list_watch_only_wallets(node: Arc<Mutex<Node>>) -> Result<impl warp::Reply, warp::Rejection> {
+    let node = node.lock().await;
+    let wallets = node.wallets.read().await.watch_only_wallets().map(|(name, wallet)| watch_only_info(name, wallet)).collect::<Vec<_>>();
+    Ok(warp::reply::with_status(warp::reply::json(&wallets), StatusCode::OK))
+}
+
+pub async fn import_watch_only_wallet(node: Arc<Mutex<Node>>, data: ImportWatchOnly) -> Result<impl warp::Reply, warp::Rejection> {
+    let node = node.lock().await;
+    let mut wallets = node.wallets.write().await;
+    match wallets.import_watch_only(&data.name, &data.addresses) {
+        Ok(wallet) => Ok(warp::reply::with_status(warp::reply::json(&watch_only_info(&data.name, wallet)), StatusCode::CREATED)),
+        Err(reason) => Ok(wallet_error_reply(reason)),
+    }
+}
+
+pub async fn load_wallet(name: String, node: Arc<Mutex<Node>>, data: LoadWallet) -> Result<impl warp::Reply, warp::Rejection> {
+    let node = node.lock().await;
+    let mut wallets = node.wallets.write().await;
+    if wallets.is_watch_only(&name) {
+        return match wallets.load_watch_only(&name) {
+            Ok(wallet) => Ok(warp::reply::with_status(warp::reply::json(&watch_only_info(&name, wallet)), StatusCode::OK)),
+            Err(reason) => Ok(wallet_error_reply(reason)),
+        };
+    }
+    match wallets.load(&name, &data.password) {
+        Ok(wallet) => Ok(warp::reply::with_status(warp::reply::json(&wallet_info(&name, wallet)), StatusCode::OK)),
+        Err(reason) => Ok(wallet_error_reply(reason)),
+    }
+}
+
+pub async fn unload_wallet(name: String, node: Arc<Mutex<Node>>) -> Result<impl warp::Reply, warp::Rejection> {
+    let node = node.lock().await;
+    let unloaded = node.wallets.write().await.unload(&name);
+    match unloaded {
+        Ok(()) => Ok(warp::reply::with_status(warp::reply::json(&name), StatusCode::OK)),
+        Err(reason) => Ok(wallet_error_reply(reason)),
+    }
+}
+
+pub async fn get_named_wallet_balance(name: String, node: Arc<Mutex<Node>>) -> Result<impl warp::Reply, warp::Rejection> {
+    let node = node.lock().await;
+    let mut wallets = node.wallets.write().await;
+    let address = match wallets.get(&name) {
+        Ok(wallet) => wallet.public_key.clone(),
+        Err(WalletError::WatchOnly { .. }) => {
+            let wallet = wallets.get_watch_only_mut(&name).unwrap();
+            let blockchain = node.blockchain.read().await;
+            wallet.sync(&blockchain);
+            let report = wallet.balance_report(&blockchain, &*node.transaction_pool.read().await, node.config.confirmations);
+            let balance = WatchOnlyBalance {
+                balance: balance(report, node.config.confirmations),
+                addresses: wallet.addresses().iter().map(|address| (address.to_string(), wallet.balance_of(address))).collect(),
+            };
+            return Ok(warp::reply::with_status(warp::reply::json(&balance), StatusCode::OK));
+        }
+        Err(reason) => return Ok(wallet_error_reply(reason)),
+    };
+    drop(wallets);
+    let balance = balance(node.balance(&address).await, node.config.confirmations);
     Ok(warp::reply::with_status(warp::reply::json(&balance), StatusCode::OK))
+}
+
+/// Confirmed and pending transactions the wallet spends or receives in.
+pub async fn get_named_wallet_transactions(name: String, node: Arc<Mutex<Node>>) -> Result<impl warp::Reply, warp::Rejection> {
+    let node = node.lock().await;
+    let mut wallets = node.wallets.write().await;
+    let address = match wallets.get(&name) {
+        Ok(wallet) => wallet.public_key.clone(),
+        Err(WalletError::WatchOnly { .. }) => {
+            let wallet = wallets.get_watch_only_mut(&name).unwrap();
+            wallet.sync(&*node.blockchain.read().await);
+            let transactions = WalletTransactions {
+                confirmed: wallet.history().to_vec(),
+                pending: wallet.pending(&*node.transaction_pool.read().await),
+            };
+            return Ok(warp::reply::with_status(warp::reply::json(&transactions), StatusCode::OK));
+        }
+        Err(reason) => return Ok(wallet_error_reply(reason)),
+    };
+    let confirmed = node.blockchain.read().await.chain.iter()
+        .flat_map(|block| block.data.iter())
+        .filter(|transaction| transaction.involves(&address))
+        .cloned()
+        .collect();
+    let pending = node.transaction_pool.read().await.transactions().into_iter()
+        .filter(|transaction| transaction.involves(&address))
+        .collect();
+    Ok(warp::reply::with_status(warp::reply::json(&WalletTransactions { confirmed, pending }), StatusCode::OK))
+}
+
+pub async fn post_named_wallet_transaction(name: String, node: Arc<Mutex<Node>>, data: TransactionData) -> Result<impl warp::Reply, warp::Rejection> {
+    let node = node.lock().await;
+    let wallet = match node.wallets.read().await.get(&name) {
+        Ok(wallet) => wallet.clone(),
+        Err(reason) => return Ok(wallet_error_reply(reason)),
+    };
+    Ok(submit_transaction(&node, wallet, data).await)
+}
+
+fn error_reply(error: String, status: StatusCode) -> warp::reply::WithStatus<warp::reply::Json> {
+    warp::reply::with_status(warp::reply::json(&ErrorMessage { error }), status)
+}
+
+pub async fn add_peer(node: Arc<Mutex<Node>>, data: PeerAddress) -> Result<impl warp::Reply, warp::Rejection> {
+    let address = match data.address.parse::<Multiaddr>() {
+        Ok(address) => address,
+        Err(e) => return Ok(error_reply(format!("Invalid multiaddr '{}': {e}", data.address), StatusCode::BAD_REQUEST)),
+    };
+    let node = node.lock().await;
+    node.command_sender.as_ref().unwrap().send(Command::Dial(address.clone())).await
+        .expect("Failed to send command to swarm");
+    let peer = PeerAddress {
+        address: address.to_string()
+    };
+    Ok(warp::reply::with_status(warp::reply::json(&peer), StatusCode::ACCEPTED))
+}
+
+pub async fn get_network(node: Arc<Mutex<Node>>) -> Result<impl warp::Reply, warp::Rejection> {
+    let node = node.lock().await;
+    let (reply, response) = oneshot::channel();
+    node.command_sender.as_ref().unwrap().send(Command::NetworkInfo(reply)).await
+        .expect("Failed to send command to swarm");
+    let network_info = response.await.expect("Swarm dropped the network info request");
+    Ok(warp::reply::with_status(warp::reply::json(&network_info), StatusCode::OK))
+}
+
+pub async fn get_status(node: Arc<Mutex<Node>>) -> Result<impl warp::Reply, warp::Rejection> {
+    let node = node.lock().await;
+    let blockchain = node.blockchain.read().await;
+    let status = NodeStatus {
+        peer_id: node.peer_id.map(|peer_id| peer_id.to_string()).unwrap_or_default(),
+        http_port: node.config.http_port.clone(),
+        chain_id: node.config.chain_id,
+        network_id: node.config.network_id(),
+        chain_height: blockchain.chain.len() - 1,
+        tip_hash: blockchain.chain.last().unwrap().hash.clone(),
+        pending_transactions: node.transaction_pool.read().await.len(),
+    };
+    Ok(warp::reply::with_status(warp::reply::json(&status), StatusCode::OK))
+}
+
+pub async fn list_peers(node: Arc<Mutex<Node>>) -> Result<impl warp::Reply, warp::Rejection> {
+    let node = node.lock().await;
+    let (reply, response) = oneshot::channel();
+    node.command_sender.as_ref().unwrap().send(Command::ListPeers(reply)).await
+        .expect("Failed to send command to swarm");
+    let peers = response.await.expect("Swarm dropped the list peers request");
+    Ok(warp::reply::with_status(warp::reply::json(&peers), StatusCode::OK))
+}
+
+pub async fn ban_peer(peer_id: String, node: Arc<Mutex<Node>>, data: BanRequest) -> Result<impl warp::Reply, warp::Rejection> {
+    let peer_id = match peer_id.parse::<PeerId>() {
+        Ok(peer_id) => peer_id,
+        Err(e) => return Ok(error_reply(format!("Invalid peer id '{peer_id}': {e}"), StatusCode::BAD_REQUEST)),
+    };
+    let duration_secs = data.duration_secs.unwrap_or(DEFAULT_BAN_DURATION_SECS);
+    let duration = match chrono::Duration::try_seconds(duration_secs as i64) {
+        Some(duration) => duration,
+        None => return Ok(error_reply(format!("Ban duration of {duration_secs}s is too long"), StatusCode::BAD_REQUEST)),
+    };
+    let node = node.lock().await;
+    let (reply, response) = oneshot::channel();
+    node.command_sender.as_ref().unwrap().send(Command::Ban(peer_id, duration, reply)).await
+        .expect("Failed to send command to swarm");
+    let banned_peer = response.await.expect("Swarm dropped the ban request");
+    Ok(warp::reply::with_status(warp::reply::json(&banned_peer), StatusCode::OK))
+}
+
+pub async fn unban_peer(peer_id: String, node: Arc<Mutex<Node>>) -> Result<impl warp::Reply, warp::Rejection> {
+    let peer_id = match peer_id.parse::<PeerId>() {
+        Ok(peer_id) => peer_id,
+        Err(e) => return Ok(error_reply(format!("Invalid peer id '{peer_id}': {e}"), StatusCode::BAD_REQUEST)),
+    };
+    let node = node.lock().await;
+    let (reply, response) = oneshot::channel();
+    node.command_sender.as_ref().unwrap().send(Command::Unban(peer_id, reply)).await
+        .expect("Failed to send command to swarm");
+    if !response.await.expect("Swarm dropped the unban request") {
+        return Ok(error_reply(format!("Peer {peer_id} is not banned"), StatusCode::NOT_FOUND));
+    }
+    Ok(warp::reply::with_status(warp::reply::json(&peer_id.to_string()), StatusCode::OK))
+}
+
+pub async fn disconnect_peer(peer_id: String, node: Arc<Mutex<Node>>) -> Result<impl warp::Reply, warp::Rejection> {
+    let peer_id = match peer_id.parse::<PeerId>() {
+        Ok(peer_id) => peer_id,
+        Err(e) => return Ok(error_reply(format!("Invalid peer id '{peer_id}': {e}"), StatusCode::BAD_REQUEST)),
+    };
+    let node = node.lock().await;
+    let (reply, response) = oneshot::channel();
+    node.command_sender.as_ref().unwrap().send(Command::Disconnect(peer_id, reply)).await
+        .expect("Failed to send command to swarm");
+    if !response.await.expect("Swarm dropped the disconnect request") {
+        return Ok(error_reply(format!("Peer {peer_id} is not connected"), StatusCode::NOT_FOUND));
+    }
+    Ok(warp::reply::with_status(warp::reply::json(&peer_id.to_string()), StatusCode::OK))
 }
\ No newline at end of file
@@ -7,7 +7,7 @@ use crate::http_server::routes;
 pub async fn run_server(node: Node) {
     let node = Arc::new(Mutex::new(node));
     let routes = build_routes(node.clone()).await;
-    let host_port: u16 = node.lock().await.host_port.parse().unwrap();
+    let host_port: u16 = node.lock().await.config.http_port.parse().unwrap();
     warp::serve(routes).run(([0, 0, 0, 0], host_port)).await;
 }
 
@@ -53,6 +53,22 @@ async fn build_routes(node: Arc<Mutex<Node>>) -> impl Filter<Extract = impl Repl
         .and(warp::body::json())
         .and_then(routes::post_transaction);
 
+    let unsigned_transaction = warp::post()
+        .and(warp::path("transactions"))
+        .and(warp::path("unsigned"))
+        .and(warp::path::end())
+        .and(node_filter.clone())
+        .and(warp::body::json())
+        .and_then(routes::post_unsigned_transaction);
+
+    let raw_transaction = warp::post()
+        .and(warp::path("transactions"))
+        .and(warp::path("raw"))
+        .and(warp::path::end())
+        .and(node_filter.clone())
+        .and(warp::body::bytes())
+        .and_then(routes::post_raw_transaction);
+
     let public_key = warp::get()
         .and(warp::path("public_key"))
         .and(warp::path::end())
@@ -65,13 +81,150 @@ async fn build_routes(node: Arc<Mutex<Node>>) -> impl Filter<Extract = impl Repl
         .and(node_filter.clone())
         .and_then(routes::get_wallet_balance);
 
+    let list_wallets = warp::get()
+        .and(warp::path("wallets"))
+        .and(warp::path::end())
+        .and(node_filter.clone())
+        .and_then(routes::list_wallets);
+
+    let create_wallet = warp::post()
+        .and(warp::path("wallets"))
+        .and(warp::path::end())
+        .and(node_filter.clone())
+        .and(warp::body::json())
+        .and_then(routes::create_wallet);
+
+    let list_watch_only_wallets = warp::get()
+        .and(warp::path("wallets"))
+        .and(warp::path("watch"))
+        .and(warp::path::end())
+        .and(node_filter.clone())
+        .and_then(routes::list_watch_only_wallets);
+
+    let import_watch_only_wallet = warp::post()
+        .and(warp::path("wallets"))
+        .and(warp::path("watch"))
+        .and(warp::path::end())
+        .and(node_filter.clone())
+        .and(warp::body::json())
+        .and_then(routes::import_watch_only_wallet);
+
+    let load_wallet = warp::post()
+        .and(warp::path("wallets"))
+        .and(warp::path::param::<String>())
+        .and(warp::path("load"))
+        .and(warp::path::end())
+        .and(node_filter.clone())
+        .and(warp::body::json())
+        .and_then(routes::load_wallet);
+
+    let unload_wallet = warp::delete()
+        .and(warp::path("wallets"))
+        .and(warp::path::param::<String>())
+        .and(warp::path::end())
+        .and(node_filter.clone())
+        .and_then(routes::unload_wallet);
+
+    let named_wallet_balance = warp::get()
+        .and(warp::path("wallets"))
+        .and(warp::path::param::<String>())
+        .and(warp::path("balance"))
+        .and(warp::path::end())
+        .and(node_filter.clone())
+        .and_then(routes::get_named_wallet_balance);
+
+    let named_wallet_transactions = warp::get()
+        .and(warp::path("wallets"))
+        .and(warp::path::param::<String>())
+        .and(warp::path("transactions"))
+        .and(warp::path::end())
+        .and(node_filter.clone())
+        .and_then(routes::get_named_wallet_transactions);
+
+    let post_named_wallet_transaction = warp::post()
+        .and(warp::path("wallets"))
+        .and(warp::path::param::<String>())
+        .and(warp::path("transactions"))
+        .and(warp::path::end())
+        .and(node_filter.clone())
+        .and(warp::body::json())
+        .and_then(routes::post_named_wallet_transaction);
+
+    let add_peer = warp::post()
+        .and(warp::path("peers"))
+        .and(warp::path::end())
+        .and(node_filter.clone())
+        .and(warp::body::json())
+        .and_then(routes::add_peer);
+
+    let network = warp::get()
+        .and(warp::path("network"))
+        .and(warp::path::end())
+        .and(node_filter.clone())
+        .and_then(routes::get_network);
+
+    let status = warp::get()
+        .and(warp::path("status"))
+        .and(warp::path::end())
+        .and(node_filter.clone())
+        .and_then(routes::get_status);
+
+    let list_peers = warp::get()
+        .and(warp::path("peers"))
+        .and(warp::path::end())
+        .and(node_filter.clone())
+        .and_then(routes::list_peers);
+
+    let ban_peer = warp::post()
+        .and(warp::path("peers"))
+        .and(warp::path::param::<String>())
+        .and(warp::path("ban"))
+        .and(warp::path::end())
+        .and(node_filter.clone())
+        .and(warp::body::json())
+        .and_then(routes::ban_peer);
+
+    let unban_peer = warp::delete()
+        .and(warp::path("peers"))
+        .and(warp::path::param::<String>())
+        .and(warp::path("ban"))
+        .and(warp::path::end())
+        .and(node_filter.clone())
+        .and_then(routes::unban_peer);
+
+    let disconnect_peer = warp::post()
+        .and(warp::path("peers"))
+        .and(warp::path::param::<String>())
+        .and(warp::path("disconnect"))
+        .and(warp::path::end())
+        .and(node_filter.clone())
+        .and_then(routes::disconnect_peer);
+
     hello
         .or(blockchain)
         .or(mine_block)
         .or(print_transactions)
         .or(post_transaction)
+        .or(unsigned_transaction)
+        .or(raw_transaction)
         .or(public_key)
         .or(wallet_balance)
+        .or(list_wallets)
+        .or(create_wallet)
+        .or(list_watch_only_wallets)
+        .or(import_watch_only_wallet)
+        .or(load_wallet)
+        .or(unload_wallet)
+        .or(named_wallet_balance)
+        .or(named_wallet_transactions)
+        .or(post_named_wallet_transaction)
+        .or(add_peer)
+        .or(network)
+        .or(status)
+        .or(list_peers)
+        .or(ban_peer)
+        .or(unban_peer)
+        .or(disconnect_peer)
         .with(cors)
         .with(warp::trace::request())
 }
\ No newline at end of file
@@ -1,62 +1,222 @@
-mod types;
+pub mod types;
+mod config;
 mod http_server;
+mod mempool_store;
 mod p2p_server;
+mod p2p_server_test;
+mod wallet_manager;
 
+use std::future::Future;
+use std::ops::DerefMut;
 use std::sync::Arc;
 use architecture::blockchain::blockchain::Blockchain;
-use architecture::wallet::wallet::Wallet;
-use architecture::wallet::transaction_pool::TransactionPool;
+use architecture::wallet::address::Address;
+use architecture::wallet::hd_wallet::HdWallet;
+use architecture::wallet::wallet::{BalanceReport, Wallet};
+use architecture::wallet::transaction_pool::{PoolError, TransactionPool};
 use tokio::sync::{mpsc, RwLock};
-use architecture::wallet::transaction::Transaction;
+use architecture::wallet::transaction::{Transaction, TransactionId, UnsignedTransaction};
 use crate::http_server::server::run_server;
-use crate::p2p_server::host::{subscribe, build_swarm};
+use crate::p2p_server::host::{subscribe, build_swarm, MyBehaviour};
+use crate::p2p_server::identity::load_or_generate_keypair;
+use crate::config::WALLETS_DIR;
+use crate::mempool_store::SavedTransaction;
+pub use crate::config::NodeConfig;
+pub use crate::wallet_manager::{WalletError, WalletManager};
+pub use crate::p2p_server::command::Command;
+pub use crate::p2p_server::protocol::NetworkMessage;
+pub use libp2p::{Multiaddr, PeerId};
+use libp2p::Swarm;
 #[derive(Clone)]
 pub struct Node{
     pub blockchain: Arc<RwLock<Blockchain>>,
-    pub host_port: String,
-    pub event_sender: Option<mpsc::Sender<String>>,
+    pub config: NodeConfig,
+    pub peer_id: Option<PeerId>,
+    pub event_sender: Option<mpsc::Sender<NetworkMessage>>,
+    pub command_sender: Option<mpsc::Sender<Command>>,
     pub wallet: Arc<RwLock<Wallet>>,
-    pub transaction_pool: Arc<RwLock<TransactionPool>>
+    /// Set when the node's keystore holds an HD wallet, `wallet` is then its first address.
+    pub hd_wallet: Option<HdWallet>,
+    pub wallets: Arc<RwLock<WalletManager>>,
+    pub transaction_pool: Arc<RwLock<TransactionPool>>,
+    // saved on the last shutdown but refused by the local tip, retried once the chain is synced
+    pub(crate) saved_transactions: Arc<RwLock<Vec<SavedTransaction>>>,
 }
 
 impl Node {
-    pub fn new(host_port: String) -> Node {
+    /// A node with a throwaway wallet, whatever is mined to it is lost on restart.
+    pub fn new(config: NodeConfig) -> Node {
+        Node::with_wallet(config, Wallet::new())
+    }
+
+    pub fn with_wallet(config: NodeConfig, mut wallet: Wallet) -> Node {
+        wallet.chain_id = config.chain_id;
         Node {
-            blockchain: Arc::new(RwLock::new(Blockchain::new())),
-            host_port,
+            blockchain: Arc::new(RwLock::new(Blockchain::with_chain_id(config.chain_id))),
+            wallet: Arc::new(RwLock::new(wallet)),
+            hd_wallet: None,
+            wallets: Arc::new(RwLock::new(WalletManager::new(config.data_dir.join(WALLETS_DIR), config.chain_id))),
+            transaction_pool: Arc::new(RwLock::new(TransactionPool::with_limits(config.mempool_limits))),
+            saved_transactions: Arc::new(RwLock::new(vec![])),
+            config,
+            peer_id: None,
             event_sender: None,
-            wallet: Arc::new(RwLock::new(Wallet::new())),
-            transaction_pool: Arc::new(RwLock::new(TransactionPool::new()))
+            command_sender: None,
         }
     }
 
+    /// A node that mines to the first address of the HD wallet and reports and spends the funds of every address
+    /// it handed out.
+    pub fn with_hd_wallet(config: NodeConfig, mut hd_wallet: HdWallet) -> Node {
+        hd_wallet.chain_id = config.chain_id;
+        // the first address is the node's own from the start
+        hd_wallet.address_count = hd_wallet.address_count.max(1);
+        let mut node = Node::with_wallet(config, hd_wallet.derive(0));
+        node.hd_wallet = Some(hd_wallet);
+        node
+    }
+
     pub async fn start(mut self) -> Result<(), Box<dyn std::error::Error>>{
-        println!("Starting blockchain client with http_port: {}", self.host_port);
-        let (event_sender, event_receiver) = mpsc::channel(100);
-        self.event_sender = Some(event_sender.clone());
-        let swarm = build_swarm()?;
-        let p2p = subscribe(self.clone(), event_receiver, swarm);
+        println!("Starting blockchain client with http_port: {}", self.config.http_port);
+        let keypair = load_or_generate_keypair(&self.config)?;
+        println!("Local peer id: {}", keypair.public().to_peer_id());
+        let swarm = build_swarm(keypair, &self.config.network_id())?;
+        self.load_mempool().await;
+        let p2p = self.attach(swarm);
         let http = run_server(self.clone());
-        _ = tokio::join!(p2p, http);
+        tokio::select! {
+            _ = async { tokio::join!(p2p, http) } => {}
+            _ = tokio::signal::ctrl_c() => {
+                println!("Shutting down");
+            }
+        }
+        self.save_mempool().await;
+        Ok(())
+    }
+
+    /// Restores the transactions saved on the last shutdown against the local tip. Those it refuses, such as ones
+    /// spending funds from blocks only peers have, are held back and retried once after the chain is synced.
+    async fn load_mempool(&self) {
+        let saved = mempool_store::load(&self.config.data_dir);
+        let total = saved.len();
+        let blockchain = self.blockchain.read().await;
+        let rejected = mempool_store::restore(saved, self.transaction_pool.write().await.deref_mut(), &blockchain);
+        println!("Restored {} of {total} saved transactions, retrying the rest once the chain is synced", total - rejected.len());
+        *self.saved_transactions.write().await = rejected.into_iter().map(|(saved, _)| saved).collect();
+    }
+
+    /// Retries the saved transactions that could not be restored at startup against the synced chain, dropping
+    /// whatever it still refuses. Only the first call after a start has anything to retry.
+    pub(crate) async fn restore_mempool(&self, blockchain: &Blockchain) {
+        let saved = std::mem::take(self.saved_transactions.write().await.deref_mut());
+        if saved.is_empty() {
+            return;
+        }
+        for (saved, e) in mempool_store::restore(saved, self.transaction_pool.write().await.deref_mut(), blockchain) {
+            println!("Dropping saved transaction {}: {e}", saved.transaction.id);
+        }
+    }
+
+    pub(crate) async fn save_mempool(&self) {
+        mempool_store::save(&self.config.data_dir, &*self.transaction_pool.read().await, &self.saved_transactions.read().await);
+    }
+
+    /// Connects the node to a swarm: creates the channels the http handlers talk to the swarm through
+    /// and returns the future that drives it.
+    pub(crate) fn attach(&mut self, swarm: Swarm<MyBehaviour>) -> impl Future<Output = Result<(), Box<dyn std::error::Error>>> {
+        self.peer_id = Some(*swarm.local_peer_id());
+        let (event_sender, event_receiver) = mpsc::channel(100);
+        self.event_sender = Some(event_sender);
+        let (command_sender, command_receiver) = mpsc::channel(100);
+        self.command_sender = Some(command_sender);
+        subscribe(self.clone(), event_receiver, command_receiver, swarm)
+    }
+
+    pub async fn submit_transaction(&self, recipient: &Address, amount: u64) -> Result<Transaction, PoolError> {
+        let wallet = self.paying_wallet(amount).await;
+        self.submit_transaction_from(wallet, recipient, amount).await
+    }
+
+    /// The wallet the node pays from. A transaction spends a single address, so with an HD wallet that is the
+    /// first address able to cover the amount on its own.
+    pub(crate) async fn paying_wallet(&self, amount: u64) -> Wallet {
+        if let Some(hd_wallet) = &self.hd_wallet {
+            for wallet in hd_wallet.wallets() {
+                if self.balance(&wallet.public_key).await.spendable >= amount {
+                    return wallet;
+                }
+            }
+        }
+        self.wallet.read().await.clone()
+    }
+
+    /// Pays from any wallet, such as one of the managed ones, and broadcasts the transaction.
+    pub async fn submit_transaction_from(&self, mut wallet: Wallet, recipient: &Address, amount: u64) -> Result<Transaction, PoolError> {
+        self.check_spendable(&wallet.public_key, amount).await?;
+        let blockchain = self.blockchain.read().await.clone();
+        let transaction = wallet.create_transaction(recipient.public_key_hex(), amount,
+                                                    self.transaction_pool.write().await.deref_mut(), &blockchain)?;
+
+        self.event_sender.as_ref().unwrap().send(NetworkMessage::Transaction(transaction.clone())).await
+            .expect("Failed to send message to event sender");
+        Ok(transaction)
+    }
+
+    /// Builds a transaction for any sender, to be signed wherever its key is kept.
+    pub async fn build_unsigned_transaction(&self, sender: &Address, recipient: &Address, amount: u64) -> Result<UnsignedTransaction, PoolError> {
+        self.check_spendable(&sender.public_key_hex(), amount).await?;
+        let blockchain = self.blockchain.read().await;
+        UnsignedTransaction::build(&sender.public_key_hex(), self.config.chain_id, recipient.public_key_hex(), amount,
+                                   &*self.transaction_pool.read().await, &blockchain)
+    }
+
+    /// Balance of `address` with the confirmations the node requires before funds can be spent.
+    pub async fn balance(&self, address: &str) -> BalanceReport {
+        Wallet::balance_report(address, &*self.blockchain.read().await, &*self.transaction_pool.read().await, self.config.confirmations)
+    }
+
+    /// Balance of the node's own wallet, over every address it handed out if it is an HD wallet.
+    pub async fn wallet_balance(&self) -> BalanceReport {
+        match &self.hd_wallet {
+            Some(hd_wallet) => hd_wallet.balance_report(&*self.blockchain.read().await, &*self.transaction_pool.read().await, self.config.confirmations),
+            None => self.balance(&self.wallet.read().await.public_key).await,
+        }
+    }
+
+    async fn check_spendable(&self, address: &str, amount: u64) -> Result<(), PoolError> {
+        let spendable = self.balance(address).await.spendable;
+        if amount > spendable {
+            return Err(PoolError::InsufficientBalance { balance: spendable, amount });
+        }
         Ok(())
     }
 
+    /// Admits a transaction signed elsewhere, by an offline signer or a client holding its own keys, to the pool and relays it.
+    pub async fn broadcast_transaction(&self, transaction: Transaction) -> Result<TransactionId, PoolError> {
+        let id = transaction.id;
+        let blockchain = self.blockchain.read().await;
+        self.transaction_pool.write().await.update_or_add_transaction(transaction.clone(), &blockchain)?;
+        self.event_sender.as_ref().unwrap().send(NetworkMessage::Transaction(transaction)).await
+            .expect("Failed to send message to event sender");
+        Ok(id)
+    }
+
     pub async fn mine(self) -> Result<(), Box<dyn std::error::Error>> {
         let mut valid_transactions = self.transaction_pool.read().await.valid_transactions();
 
         let wallet = self.wallet.read().await.clone();
-        valid_transactions.push(Transaction::reward_transaction(&wallet, &Wallet::blockchain_wallet()));
+        let mut blockchain_wallet = Wallet::blockchain_wallet();
+        blockchain_wallet.chain_id = self.config.chain_id;
+        let fees = valid_transactions.iter().try_fold(0u64, |total, transaction| total.checked_add(transaction.fee()))
+            .ok_or("fees of the pending transactions overflow")?;
+        valid_transactions.push(Transaction::reward_transaction_with_fees(&wallet, &blockchain_wallet, fees));
 
         let mut blockchain = self.blockchain.write().await;
-        blockchain.add_block(valid_transactions);
-
-        let mut mew_chain_json = serde_json::to_string(&blockchain.chain).unwrap();
-        mew_chain_json = "blockchain: ".to_string() + &mew_chain_json;
-        self.event_sender.as_ref().unwrap().send(mew_chain_json).await
-            .expect("Failed to send message to event sender");
+        let block = blockchain.add_block(valid_transactions);
+        // peers drop the same transactions once they connect the block
+        self.transaction_pool.write().await.remove_confirmed(&[block]);
 
-        self.transaction_pool.write().await.transactions.clear();
-        self.event_sender.as_ref().unwrap().send("transaction_pool_clear: clear".to_string()).await
+        self.event_sender.as_ref().unwrap().send(NetworkMessage::Blockchain(blockchain.chain.clone())).await
             .expect("Failed to send message to event sender");
 
         Ok(())
@@ -0,0 +1,54 @@
+use std::fs;
+use std::path::Path;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use architecture::blockchain::blockchain::Blockchain;
+use architecture::wallet::transaction::Transaction;
+use architecture::wallet::transaction_pool::{PoolError, TransactionPool};
+use crate::config::MEMPOOL_FILE;
+
+/// A pending transaction as saved on shutdown, with the time it was first admitted so that restarting a node
+/// does not reset its expiry.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub(crate) struct SavedTransaction {
+    pub(crate) transaction: Transaction,
+    pub(crate) added_at: DateTime<Utc>,
+}
+
+/// Reads the transactions saved by `save`, without admitting them to any pool yet.
+pub(crate) fn load(data_dir: &Path) -> Vec<SavedTransaction> {
+    let mempool_file = data_dir.join(MEMPOOL_FILE);
+    match fs::read_to_string(&mempool_file) {
+        Ok(json) => serde_json::from_str::<Vec<SavedTransaction>>(&json).unwrap_or_else(|e| {
+            println!("Ignoring malformed mempool {}: {e}", mempool_file.display());
+            vec![]
+        }),
+        Err(_) => vec![],
+    }
+}
+
+/// Re-admits saved transactions. Each one goes through the pool's admission checks again, so whatever the chain
+/// tip no longer allows is refused. Returns the refused transactions and why.
+pub(crate) fn restore(saved: Vec<SavedTransaction>, transaction_pool: &mut TransactionPool, blockchain: &Blockchain) -> Vec<(SavedTransaction, PoolError)> {
+    saved.into_iter()
+        .filter_map(|saved| transaction_pool.restore_transaction(saved.transaction.clone(), blockchain, saved.added_at)
+            .err()
+            .map(|e| (saved, e)))
+        .collect()
+}
+
+/// Saves the pending transactions, along with saved ones still waiting for the chain to sync.
+pub(crate) fn save(data_dir: &Path, transaction_pool: &TransactionPool, unrestored: &[SavedTransaction]) {
+    let mempool_file = data_dir.join(MEMPOOL_FILE);
+    let _ = fs::create_dir_all(data_dir);
+    let saved = transaction_pool.transactions().into_iter()
+        .map(|transaction| SavedTransaction { added_at: transaction_pool.added_at(&transaction.id).unwrap(), transaction })
+        .chain(unrestored.iter().cloned())
+        .collect::<Vec<_>>();
+    // written next to the target and renamed, so a crash mid-write never leaves a truncated file behind
+    let temp_file = mempool_file.with_extension("json.tmp");
+    let json = serde_json::to_string(&saved).unwrap();
+    if let Err(e) = fs::write(&temp_file, json).and_then(|_| fs::rename(&temp_file, &mempool_file)) {
+        println!("Cannot save mempool {}: {e}", mempool_file.display());
+    }
+}
@@ -0,0 +1,70 @@
+use std::collections::HashMap;
+use libp2p::{Multiaddr, PeerId, Swarm};
+use libp2p::swarm::ConnectionId;
+use libp2p::swarm::dial_opts::DialOpts;
+use crate::p2p_server::host::MyBehaviour;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum DialState {
+    Disconnected,
+    Dialing(ConnectionId),
+    Connected(PeerId, ConnectionId),
+}
+
+/// Peers the node keeps a connection to regardless of mDNS, redialed whenever the connection drops.
+pub(crate) struct BootstrapPeers {
+    peers: HashMap<Multiaddr, DialState>,
+}
+
+impl BootstrapPeers {
+    pub(crate) fn new(addresses: &[Multiaddr]) -> BootstrapPeers {
+        BootstrapPeers {
+            peers: addresses.iter().map(|address| (address.clone(), DialState::Disconnected)).collect(),
+        }
+    }
+
+    pub(crate) fn add(&mut self, address: Multiaddr) {
+        self.peers.entry(address).or_insert(DialState::Disconnected);
+    }
+
+    pub(crate) fn dial_disconnected(&mut self, swarm: &mut Swarm<MyBehaviour>) {
+        for (address, state) in self.peers.iter_mut() {
+            if *state != DialState::Disconnected {
+                continue;
+            }
+            let opts = DialOpts::unknown_peer_id().address(address.clone()).build();
+            let connection_id = opts.connection_id();
+            match swarm.dial(opts) {
+                Ok(()) => {
+                    println!("Dialing {address}");
+                    *state = DialState::Dialing(connection_id);
+                }
+                Err(e) => println!("Cannot dial {address}: {e}"),
+            }
+        }
+    }
+
+    pub(crate) fn on_connection_established(&mut self, peer_id: PeerId, connection_id: ConnectionId) {
+        for state in self.peers.values_mut() {
+            if *state == DialState::Dialing(connection_id) {
+                *state = DialState::Connected(peer_id, connection_id);
+            }
+        }
+    }
+
+    /// Returns true if the failed or closed connection belonged to a bootstrap peer.
+    pub(crate) fn on_connection_lost(&mut self, connection_id: ConnectionId) -> bool {
+        let mut lost = false;
+        for state in self.peers.values_mut() {
+            let matches = match *state {
+                DialState::Dialing(id) | DialState::Connected(_, id) => id == connection_id,
+                DialState::Disconnected => false,
+            };
+            if matches {
+                *state = DialState::Disconnected;
+                lost = true;
+            }
+        }
+        lost
+    }
+}
@@ -0,0 +1,18 @@
+use chrono::Duration;
+use libp2p::{Multiaddr, PeerId};
+use tokio::sync::oneshot;
+use crate::types::dto::{BannedPeer, NetworkInfo, Peers};
+
+/// Requests from the http handlers that have to be carried out by the swarm itself
+/// rather than broadcast to peers.
+#[derive(Debug)]
+pub enum Command {
+    Dial(Multiaddr),
+    NetworkInfo(oneshot::Sender<NetworkInfo>),
+    ListPeers(oneshot::Sender<Peers>),
+    Ban(PeerId, Duration, oneshot::Sender<BannedPeer>),
+    /// Replies with false if the peer was not banned.
+    Unban(PeerId, oneshot::Sender<bool>),
+    /// Replies with false if the peer was not connected.
+    Disconnect(PeerId, oneshot::Sender<bool>),
+}
@@ -0,0 +1,78 @@
+use libp2p::{identify, kad, PeerId, Swarm};
+use crate::p2p_server::host::MyBehaviour;
+use crate::p2p_server::protocol::KAD_PROTOCOL;
+use crate::types::dto::{KnownPeer, NetworkInfo};
+
+pub(crate) fn routing_table_size(swarm: &mut Swarm<MyBehaviour>) -> usize {
+    swarm.behaviour_mut().kademlia.kbuckets().map(|bucket| bucket.num_entries()).sum()
+}
+
+/// Starts a Kademlia bootstrap query, which walks the DHT towards our own id and fills the routing table.
+pub(crate) fn bootstrap(swarm: &mut Swarm<MyBehaviour>) {
+    // fails only while the routing table is still empty, the next discovery tick retries
+    let _ = swarm.behaviour_mut().kademlia.bootstrap();
+}
+
+pub(crate) fn handle_identify_event(swarm: &mut Swarm<MyBehaviour>, event: identify::Event) {
+    if let identify::Event::Received { peer_id, info } = event {
+        if !info.protocols.contains(&KAD_PROTOCOL) {
+            return;
+        }
+        let was_empty = routing_table_size(swarm) == 0;
+        for address in info.listen_addrs {
+            swarm.behaviour_mut().kademlia.add_address(&peer_id, address);
+        }
+        if was_empty {
+            bootstrap(swarm);
+        }
+    }
+}
+
+pub(crate) fn handle_kademlia_event(swarm: &mut Swarm<MyBehaviour>, event: kad::Event) {
+    match event {
+        kad::Event::RoutingUpdated { peer, is_new_peer: true, .. } => {
+            println!("Kademlia added {peer} to the routing table");
+            dial_if_disconnected(swarm, peer);
+        }
+        kad::Event::OutboundQueryProgressed { result: kad::QueryResult::Bootstrap(Ok(ok)), .. } => {
+            dial_if_disconnected(swarm, ok.peer);
+        }
+        kad::Event::OutboundQueryProgressed { result: kad::QueryResult::GetClosestPeers(Ok(ok)), .. } => {
+            for peer in ok.peers {
+                dial_if_disconnected(swarm, peer);
+            }
+        }
+        _ => {}
+    }
+}
+
+fn dial_if_disconnected(swarm: &mut Swarm<MyBehaviour>, peer_id: PeerId) {
+    if peer_id == *swarm.local_peer_id() || swarm.is_connected(&peer_id) {
+        return;
+    }
+    // kademlia supplies the addresses it has learned for the peer
+    if let Err(e) = swarm.dial(peer_id) {
+        println!("Cannot dial discovered peer {peer_id}: {e}");
+    }
+}
+
+pub(crate) fn network_info(swarm: &mut Swarm<MyBehaviour>) -> NetworkInfo {
+    let mut known_peers = vec![];
+    for bucket in swarm.behaviour_mut().kademlia.kbuckets() {
+        for entry in bucket.iter() {
+            known_peers.push(KnownPeer {
+                peer_id: entry.node.key.preimage().to_string(),
+                addresses: entry.node.value.iter().map(|address| address.to_string()).collect(),
+                connected: false,
+            });
+        }
+    }
+    for peer in known_peers.iter_mut() {
+        peer.connected = peer.peer_id.parse().is_ok_and(|peer_id| swarm.is_connected(&peer_id));
+    }
+    NetworkInfo {
+        routing_table_size: known_peers.len(),
+        connected_peers: swarm.connected_peers().count(),
+        known_peers,
+    }
+}
@@ -1,89 +1,164 @@
 use futures::stream::StreamExt;
-use libp2p::{gossipsub, mdns, noise, PeerId, Swarm, swarm::NetworkBehaviour, swarm::SwarmEvent, tcp, yamux};
+use chrono::{DateTime, Utc};
+use libp2p::{allow_block_list, connection_limits, gossipsub, identify, kad, mdns, noise, PeerId, Swarm, swarm::NetworkBehaviour, swarm::SwarmEvent, tcp, yamux};
 use std::collections::hash_map::DefaultHasher;
 use std::error::Error;
+use std::fmt;
 use std::hash::{Hash, Hasher};
 use std::time::Duration;
 use libp2p::gossipsub::{IdentTopic};
+use libp2p::identity::Keypair;
+use libp2p::swarm::behaviour::toggle::Toggle;
 use tokio::{select};
+use tokio::time::interval;
 use tokio::sync::mpsc::{Receiver};
 use tracing_subscriber::EnvFilter;
-use architecture::blockchain::block::Block;
-use architecture::wallet::transaction::Transaction;
+use architecture::blockchain::blockchain::ChainError;
+use architecture::wallet::transaction_pool::PoolError;
 use crate::Node;
+use crate::config::{DISCOVERY_INTERVAL, INVALID_MESSAGE_PENALTY, MAX_CONNECTIONS_PER_PEER, MEMPOOL_EXPIRY_INTERVAL, MEMPOOL_SAVE_INTERVAL, REDIAL_INTERVAL, VALID_MESSAGE_SCORE};
+use crate::p2p_server::bootstrap::BootstrapPeers;
+use crate::p2p_server::command::Command;
+use crate::p2p_server::discovery;
+use crate::p2p_server::peers::PeerBook;
+use crate::types::dto::{BannedPeer, Peers};
+use crate::p2p_server::protocol::{identify_protocol_version, topic_name, BLOCKCHAIN_TOPIC, KAD_PROTOCOL, NetworkMessage, TOPICS};
 
 #[derive(NetworkBehaviour)]
 pub(crate) struct MyBehaviour {
     pub(crate) gossipsub: gossipsub::Behaviour,
-    mdns: mdns::tokio::Behaviour,
+    mdns: Toggle<mdns::tokio::Behaviour>,
+    pub(crate) kademlia: kad::Behaviour<kad::store::MemoryStore>,
+    identify: identify::Behaviour,
+    block_list: allow_block_list::Behaviour<allow_block_list::BlockedPeers>,
+    connection_limits: connection_limits::Behaviour,
 }
 
 
 
 
-pub async fn subscribe(mut node: Node, mut event_receiver: Receiver<String>, mut swarm: Swarm<MyBehaviour>) -> Result<(), Box<dyn Error>> {
+pub async fn subscribe(mut node: Node, mut event_receiver: Receiver<NetworkMessage>, mut command_receiver: Receiver<Command>, mut swarm: Swarm<MyBehaviour>) -> Result<(), Box<dyn Error>> {
 
-    swarm.listen_on("/ip4/0.0.0.0/udp/0/quic-v1".parse()?)?;
-    swarm.listen_on("/ip4/0.0.0.0/tcp/0".parse()?)?;
-
-    let blockchain_topic = IdentTopic::new("blockchain");
-    swarm.behaviour_mut().gossipsub.subscribe(&blockchain_topic)?;
-
-    let transaction_pool_topic = IdentTopic::new("transaction_pool");
-    swarm.behaviour_mut().gossipsub.subscribe(&transaction_pool_topic)?;
+    for address in &node.config.listen_addresses {
+        swarm.listen_on(address.clone())?;
+    }
 
-    let transaction_pool_clear_topic = IdentTopic::new("transaction_pool_clear");
-    swarm.behaviour_mut().gossipsub.subscribe(&transaction_pool_clear_topic)?;
+    let network_id = node.config.network_id();
+    println!("Joining network {network_id} (chain id {})", node.config.chain_id);
+    for topic in TOPICS {
+        swarm.behaviour_mut().gossipsub.subscribe(&IdentTopic::new(topic_name(&network_id, topic)))?;
+    }
 
+    let mut bootstrap_peers = BootstrapPeers::new(&node.config.bootstrap_peers);
+    let mut peer_book = PeerBook::load(&node.config.data_dir);
+    for peer_id in peer_book.banned_peer_ids() {
+        swarm.behaviour_mut().block_list.block_peer(peer_id);
+    }
+    let mut redial = interval(REDIAL_INTERVAL);
+    let mut discovery = interval(DISCOVERY_INTERVAL);
+    let mut mempool_expiry = interval(MEMPOOL_EXPIRY_INTERVAL);
+    let mut mempool_save = interval(MEMPOOL_SAVE_INTERVAL);
 
     loop {
         select! {
             Some(event) = swarm.next() => {
                 // dbg!(event);
-                handle_event(&mut swarm, event, &mut node).await;
+                handle_event(&mut swarm, &network_id, event, &mut node, &mut bootstrap_peers, &mut peer_book).await;
             }
-            Some(data) = event_receiver.recv() => {
-                process_input(&mut swarm, &blockchain_topic, &transaction_pool_topic, &transaction_pool_clear_topic, data);
+            Some(message) = event_receiver.recv() => {
+                send_message(&mut swarm, &network_id, &message);
+            }
+            Some(command) = command_receiver.recv() => {
+                handle_command(&mut swarm, command, &mut bootstrap_peers, &mut peer_book);
+            }
+            _ = redial.tick() => {
+                for peer_id in peer_book.expire_bans(Utc::now()) {
+                    println!("Ban of {peer_id} expired");
+                    swarm.behaviour_mut().block_list.unblock_peer(peer_id);
+                }
+                bootstrap_peers.dial_disconnected(&mut swarm);
+            }
+            _ = discovery.tick() => {
+                discovery::bootstrap(&mut swarm);
+            }
+            _ = mempool_expiry.tick() => {
+                for id in node.transaction_pool.write().await.expire(Utc::now()) {
+                    println!("Transaction {id} expired from the pool");
+                }
+            }
+            _ = mempool_save.tick() => {
+                node.save_mempool().await;
             }
-
         }
     }
 }
 
-pub(crate) fn send_message(swarm: &mut Swarm<MyBehaviour>, topic: &IdentTopic, message: String) {
-    if let Err(e) = swarm.behaviour_mut().gossipsub.publish(topic.clone(), message.as_bytes()) {
-        println!("Publish error: {e:?}");
+fn handle_command(swarm: &mut Swarm<MyBehaviour>, command: Command, bootstrap_peers: &mut BootstrapPeers, peer_book: &mut PeerBook) {
+    match command {
+        Command::Dial(address) => {
+            bootstrap_peers.add(address);
+            bootstrap_peers.dial_disconnected(swarm);
+        }
+        Command::NetworkInfo(reply) => {
+            let _ = reply.send(discovery::network_info(swarm));
+        }
+        Command::ListPeers(reply) => {
+            let gossipsub = &swarm.behaviour().gossipsub;
+            let topics = |peer_id: &PeerId| gossipsub.all_peers()
+                .find(|(id, _)| *id == peer_id)
+                .map(|(_, topics)| topics.iter().map(|topic| topic.to_string()).collect())
+                .unwrap_or_default();
+            let _ = reply.send(Peers {
+                connected: peer_book.connected_peers(topics),
+                banned: peer_book.banned_peers(),
+            });
+        }
+        Command::Ban(peer_id, duration, reply) => {
+            let until = Utc::now().checked_add_signed(duration).unwrap_or(DateTime::<Utc>::MAX_UTC);
+            println!("Banning {peer_id} until {until}");
+            peer_book.ban(peer_id, until);
+            // blocking also closes every open connection to the peer
+            swarm.behaviour_mut().block_list.block_peer(peer_id);
+            swarm.behaviour_mut().gossipsub.remove_explicit_peer(&peer_id);
+            let _ = reply.send(BannedPeer {
+                peer_id: peer_id.to_string(),
+                until,
+            });
+        }
+        Command::Unban(peer_id, reply) => {
+            swarm.behaviour_mut().block_list.unblock_peer(peer_id);
+            let _ = reply.send(peer_book.unban(&peer_id));
+        }
+        Command::Disconnect(peer_id, reply) => {
+            let _ = reply.send(peer_book.is_connected(&peer_id) && swarm.disconnect_peer_id(peer_id).is_ok());
+        }
     }
 }
 
-
-fn process_input(swarm: &mut Swarm<MyBehaviour>, blockchain_topic: &IdentTopic, transaction_pool_topic: &IdentTopic, transaction_pool_clear_topic: &IdentTopic, line: String) {
-    let mut parts = line.splitn(2, ": ");
-    if let (Some(topic_str), Some(message)) = (parts.next(), parts.next()) {
-        let topic = match topic_str {
-            "blockchain" => blockchain_topic,
-            "transaction_pool" => transaction_pool_topic,
-            "transaction_pool_clear" => transaction_pool_clear_topic,
-            _ => {
-                println!("Unknown topic: {topic_str}");
-                return;
-            }
-        };
-        send_message(swarm, topic, message.to_string());
-    } else {
-        println!("Invalid message format. Use '<topic>: <message>'");
+pub(crate) fn send_message(swarm: &mut Swarm<MyBehaviour>, network_id: &str, message: &NetworkMessage) {
+    let data = match message.encode() {
+        Ok(data) => data,
+        Err(e) => {
+            println!("Cannot encode {message}: {e}");
+            return;
+        }
+    };
+    if let Err(e) = swarm.behaviour_mut().gossipsub.publish(IdentTopic::new(topic_name(network_id, message.topic())), data) {
+        println!("Publish error: {e:?}");
     }
 }
 
-async fn handle_event(swarm: &mut Swarm<MyBehaviour>, event: SwarmEvent<MyBehaviourEvent>, node: &mut Node) {
+async fn handle_event(swarm: &mut Swarm<MyBehaviour>, network_id: &str, event: SwarmEvent<MyBehaviourEvent>, node: &mut Node, bootstrap_peers: &mut BootstrapPeers, peer_book: &mut PeerBook) {
 
     match event {
         SwarmEvent::Behaviour(MyBehaviourEvent::Mdns(mdns::Event::Discovered(list))) => {
-            for (peer_id, _multiaddr) in list {
+            for (peer_id, multiaddr) in list {
                 println!("mDNS discovered a new peer: {peer_id}");
                 swarm.behaviour_mut().gossipsub.add_explicit_peer(&peer_id);
+                swarm.behaviour_mut().kademlia.add_address(&peer_id, multiaddr);
             }
-            send_message(swarm, &IdentTopic::new("blockchain"), serde_json::to_string(&node.blockchain.read().await.chain).unwrap());
+            let chain = node.blockchain.read().await.chain.clone();
+            send_message(swarm, network_id, &NetworkMessage::Blockchain(chain));
         },
 
         SwarmEvent::Behaviour(MyBehaviourEvent::Mdns(mdns::Event::Expired(list))) => {
@@ -98,19 +173,78 @@ async fn handle_event(swarm: &mut Swarm<MyBehaviour>, event: SwarmEvent<MyBehavi
                                                               message,
                                                           })) => {
             let self_id = swarm.local_peer_id();
-            if peer_id == self_id.clone() {
+            if peer_id == *self_id {
                 return;
             }
-            match_topic_message(&message.topic.as_str(), &String::from_utf8_lossy(&message.data), id, &peer_id, node).await;
+            let author = message.source.unwrap_or(peer_id);
+            match NetworkMessage::decode(&message.data) {
+                Ok(network_message) if topic_name(network_id, network_message.topic()) == message.topic.as_str() => {
+                    if let NetworkMessage::Blockchain(chain) = &network_message {
+                        peer_book.record_tip(author, chain.len().saturating_sub(1));
+                    }
+                    match handle_message(network_message, &id, &peer_id, node).await {
+                        Ok(()) => peer_book.adjust_score(peer_id, VALID_MESSAGE_SCORE),
+                        Err(e) => {
+                            println!("Rejected message with id: {id} from peer: {peer_id}: {e}");
+                            if e.is_invalid() {
+                                peer_book.adjust_score(peer_id, INVALID_MESSAGE_PENALTY);
+                            }
+                        }
+                    }
+                }
+                Ok(network_message) => {
+                    println!("Dropping {network_message} with id: {id} from peer: {peer_id}, it was sent on topic '{}'", message.topic);
+                    peer_book.adjust_score(peer_id, INVALID_MESSAGE_PENALTY);
+                }
+                Err(e) => {
+                    println!("Dropping message with id: {id} from peer: {peer_id}: {e}");
+                    peer_book.adjust_score(peer_id, INVALID_MESSAGE_PENALTY);
+                }
+            }
         },
+        SwarmEvent::Behaviour(MyBehaviourEvent::Identify(event)) => {
+            if let identify::Event::Received { peer_id, info } = &event {
+                let expected = identify_protocol_version(network_id);
+                if info.protocol_version != expected {
+                    println!("Disconnecting {peer_id}: it speaks '{}' but this node speaks '{expected}'", info.protocol_version);
+                    swarm.behaviour_mut().gossipsub.remove_explicit_peer(peer_id);
+                    swarm.behaviour_mut().kademlia.remove_peer(peer_id);
+                    let _ = swarm.disconnect_peer_id(*peer_id);
+                    return;
+                }
+                peer_book.on_identified(*peer_id, info);
+            }
+            discovery::handle_identify_event(swarm, event);
+        }
+        SwarmEvent::Behaviour(MyBehaviourEvent::Kademlia(event)) => {
+            discovery::handle_kademlia_event(swarm, event);
+        }
         SwarmEvent::NewListenAddr { address, .. } => {
             println!("Local node is listening on {address}");
         }
+        SwarmEvent::ConnectionEstablished { peer_id, connection_id, endpoint, .. } => {
+            println!("Connected to {peer_id} at {}", endpoint.get_remote_address());
+            bootstrap_peers.on_connection_established(peer_id, connection_id);
+            peer_book.on_connected(peer_id, endpoint.get_remote_address().clone());
+        }
+        SwarmEvent::ConnectionClosed { peer_id, connection_id, num_established, .. } => {
+            println!("Connection to {peer_id} closed");
+            if num_established == 0 {
+                peer_book.on_disconnected(peer_id);
+            }
+            if bootstrap_peers.on_connection_lost(connection_id) {
+                bootstrap_peers.dial_disconnected(swarm);
+            }
+        }
+        SwarmEvent::OutgoingConnectionError { connection_id, error, .. } if bootstrap_peers.on_connection_lost(connection_id) => {
+            println!("Cannot reach bootstrap peer, retrying in {}s: {error}", REDIAL_INTERVAL.as_secs());
+        }
         SwarmEvent::Behaviour(MyBehaviourEvent::Gossipsub(gossipsub::Event::Subscribed { peer_id, topic })) => {
             println!("Subscribed to '{topic}' from {peer_id}");
             let self_id = swarm.local_peer_id();
-            if topic.as_str() == "blockchain" && peer_id != self_id.clone(){
-                send_message(swarm, &IdentTopic::new("blockchain"), serde_json::to_string(&node.blockchain.read().await.chain).unwrap());
+            if topic.as_str() == topic_name(network_id, BLOCKCHAIN_TOPIC) && peer_id != *self_id{
+                let chain = node.blockchain.read().await.chain.clone();
+                send_message(swarm, network_id, &NetworkMessage::Blockchain(chain));
             }
         }
         _ => {
@@ -119,38 +253,61 @@ async fn handle_event(swarm: &mut Swarm<MyBehaviour>, event: SwarmEvent<MyBehavi
     }
 }
 
-async fn match_topic_message(topic: &str, msg: &str, id: gossipsub::MessageId, peer_id: &PeerId, node: &mut Node) {
-    match topic {
-        "blockchain" => {
-            println!("Received blockchain message: '{msg}' with id: {id} from peer: {peer_id}");
-            let new_chain = serde_json::from_str::<Vec<Block>>(&msg).unwrap();
-            // for block in &new_chain {
-            //     println!("{block}");
-            // }
-            node.blockchain.write().await.replace_chain(new_chain);
+/// Why a gossiped message was not applied.
+#[derive(Debug)]
+enum MessageError {
+    Chain(ChainError),
+    Transaction(PoolError),
+}
 
+impl MessageError {
+    /// Whether the sender should be penalised, see `PoolError::is_invalid`. A received chain is only ever
+    /// refused for being invalid, a merely shorter one is not an error.
+    fn is_invalid(&self) -> bool {
+        match self {
+            MessageError::Chain(_) => true,
+            MessageError::Transaction(e) => e.is_invalid(),
+        }
+    }
+}
+
+impl fmt::Display for MessageError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MessageError::Chain(e) => write!(f, "{e}"),
+            MessageError::Transaction(e) => write!(f, "{e}"),
+        }
+    }
+}
+
+async fn handle_message(message: NetworkMessage, id: &gossipsub::MessageId, peer_id: &PeerId, node: &mut Node) -> Result<(), MessageError> {
+    println!("Received {message} with id: {id} from peer: {peer_id}");
+    match message {
+        NetworkMessage::Blockchain(new_chain) => {
+            let mut blockchain = node.blockchain.write().await;
+            let disconnected = blockchain.disconnected_by(&new_chain);
+            if blockchain.replace_chain(new_chain).map_err(MessageError::Chain)? {
+                let mut transaction_pool = node.transaction_pool.write().await;
+                transaction_pool.remove_confirmed(&blockchain.chain);
+                transaction_pool.readd_disconnected(&disconnected, &blockchain);
+                drop(transaction_pool);
+                node.restore_mempool(&blockchain).await;
+            }
         },
-        "transaction_pool" => {
-            println!("Received transaction_pool message: '{msg}' with id: {id} from peer: {peer_id}");
-            let transaction = serde_json::from_str::<Transaction>(&msg).unwrap();
-            // println!("{transaction}");
-            node.transaction_pool.write().await.update_or_add_transaction(transaction);
-        },
-        "transaction_pool_clear" => {
-            println!("Received transaction_pool_clear message: '{msg}' with id: {id} from peer: {peer_id}");
-            node.transaction_pool.write().await.transactions.clear();
+        NetworkMessage::Transaction(transaction) => {
+            let blockchain = node.blockchain.read().await;
+            node.transaction_pool.write().await.update_or_add_transaction(transaction, &blockchain)
+                .map_err(MessageError::Transaction)?;
         },
-        _ => {
-            // println!("Received message on unknown topic '{topic}': '{msg}' with id: {id} from peer: {peer_id}");
-        }
     }
+    Ok(())
 }
 
-pub fn build_swarm() -> Result<libp2p::Swarm<MyBehaviour>, Box<dyn Error>> {
+pub fn build_swarm(keypair: Keypair, network_id: &str) -> Result<libp2p::Swarm<MyBehaviour>, Box<dyn Error>> {
     let _ = tracing_subscriber::fmt()
         .with_env_filter(EnvFilter::from_default_env())
         .try_init();
-    let swarm = libp2p::SwarmBuilder::with_new_identity()
+    let swarm = libp2p::SwarmBuilder::with_existing_identity(keypair)
         .with_tokio()
         .with_tcp(
             tcp::Config::default(),
@@ -158,36 +315,84 @@ pub fn build_swarm() -> Result<libp2p::Swarm<MyBehaviour>, Box<dyn Error>> {
             yamux::Config::default,
         )?
         .with_quic()
-        .with_behaviour(|key| {
-            let message_id_fn = |message: &gossipsub::Message| {
-                let mut s = DefaultHasher::new();
-                message.data.hash(&mut s);
-                gossipsub::MessageId::from(s.finish().to_string())
-            };
-
-            let gossipsub_config = gossipsub::ConfigBuilder::default()
-                .heartbeat_interval(Duration::from_secs(1))
-                .validation_mode(gossipsub::ValidationMode::Permissive)
-                .duplicate_cache_time(Duration::from_secs(0))
-                .message_id_fn(message_id_fn)
-                .published_message_ids_cache_time(Duration::from_secs(0))
-                .build()
-                .map_err(|msg| std::io::Error::new(std::io::ErrorKind::Other, msg))?;
-
-            let gossipsub = gossipsub::Behaviour::new(
-                gossipsub::MessageAuthenticity::Signed(key.clone()),
-                gossipsub_config,
-            )?;
-
-            let mdns =
-                mdns::tokio::Behaviour::new(mdns::Config::default(), key.public().to_peer_id())?;
-            Ok(MyBehaviour { gossipsub, mdns })
+        .with_behaviour(|key| build_behaviour(key, network_id, true))?
+        .with_swarm_config(|c| c.with_idle_connection_timeout(Duration::from_secs(60)))
+        .build();
+
+    Ok(swarm)
+}
+
+/// Builds a swarm that only speaks libp2p's in-process memory transport, so that several nodes
+/// can share one test process without touching real sockets. mDNS is disabled as it needs a network.
+#[cfg(test)]
+pub(crate) fn build_memory_swarm(keypair: Keypair, network_id: &str) -> Result<libp2p::Swarm<MyBehaviour>, Box<dyn Error>> {
+    use libp2p::core::transport::{MemoryTransport, Transport, upgrade::Version};
+
+    let swarm = libp2p::SwarmBuilder::with_existing_identity(keypair)
+        .with_tokio()
+        .with_other_transport(|key| {
+            Ok::<_, Box<dyn Error + Send + Sync>>(MemoryTransport::default()
+                .upgrade(Version::V1)
+                .authenticate(noise::Config::new(key)?)
+                .multiplex(yamux::Config::default())
+                .boxed())
         })?
+        .with_behaviour(|key| build_behaviour(key, network_id, false))?
         .with_swarm_config(|c| c.with_idle_connection_timeout(Duration::from_secs(60)))
         .build();
 
     Ok(swarm)
 }
 
+fn build_behaviour(key: &Keypair, network_id: &str, enable_mdns: bool) -> Result<MyBehaviour, Box<dyn Error + Send + Sync>> {
+    let message_id_fn = |message: &gossipsub::Message| {
+        let mut s = DefaultHasher::new();
+        message.data.hash(&mut s);
+        gossipsub::MessageId::from(s.finish().to_string())
+    };
 
+    let gossipsub_config = gossipsub::ConfigBuilder::default()
+        .heartbeat_interval(Duration::from_secs(1))
+        .validation_mode(gossipsub::ValidationMode::Permissive)
+        .duplicate_cache_time(Duration::from_secs(0))
+        .message_id_fn(message_id_fn)
+        .published_message_ids_cache_time(Duration::from_secs(0))
+        .build()
+        .map_err(std::io::Error::other)?;
 
+    let gossipsub = gossipsub::Behaviour::new(
+        gossipsub::MessageAuthenticity::Signed(key.clone()),
+        gossipsub_config,
+    )?;
+
+    let mdns = if enable_mdns {
+        Some(mdns::tokio::Behaviour::new(mdns::Config::default(), key.public().to_peer_id())?)
+    } else {
+        None
+    };
+
+    let mut kademlia_config = kad::Config::default();
+    kademlia_config.set_protocol_names(vec![KAD_PROTOCOL]);
+    let mut kademlia = kad::Behaviour::with_config(
+        key.public().to_peer_id(),
+        kad::store::MemoryStore::new(key.public().to_peer_id()),
+        kademlia_config,
+    );
+    // nodes on private networks would otherwise stay in client mode and never answer queries
+    kademlia.set_mode(Some(kad::Mode::Server));
+
+    let identify = identify::Behaviour::new(identify::Config::new(identify_protocol_version(network_id), key.public()));
+
+    let connection_limits = connection_limits::Behaviour::new(
+        connection_limits::ConnectionLimits::default().with_max_established_per_peer(Some(MAX_CONNECTIONS_PER_PEER)),
+    );
+
+    Ok(MyBehaviour {
+        gossipsub,
+        mdns: mdns.into(),
+        kademlia,
+        identify,
+        block_list: allow_block_list::Behaviour::default(),
+        connection_limits,
+    })
+}
@@ -0,0 +1,41 @@
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+use libp2p::identity::Keypair;
+use crate::config::{NodeConfig, NODE_KEY_FILE};
+
+/// Loads the libp2p keypair that determines the node's `PeerId`.
+/// An explicitly configured key file has to exist, otherwise the key is kept in the data directory
+/// and generated on first run.
+pub(crate) fn load_or_generate_keypair(config: &NodeConfig) -> Result<Keypair, Box<dyn Error>> {
+    if let Some(key_file) = &config.node_key_file {
+        return read_keypair(key_file);
+    }
+    let key_file = config.data_dir.join(NODE_KEY_FILE);
+    if key_file.exists() {
+        return read_keypair(&key_file);
+    }
+    let keypair = Keypair::generate_ed25519();
+    write_keypair(&key_file, &keypair)?;
+    println!("Generated new node identity in {}", key_file.display());
+    Ok(keypair)
+}
+
+fn read_keypair(path: &Path) -> Result<Keypair, Box<dyn Error>> {
+    let bytes = fs::read(path).map_err(|e| format!("Cannot read node key {}: {e}", path.display()))?;
+    let keypair = Keypair::from_protobuf_encoding(&bytes).map_err(|e| format!("Invalid node key {}: {e}", path.display()))?;
+    Ok(keypair)
+}
+
+fn write_keypair(path: &Path, keypair: &Keypair) -> Result<(), Box<dyn Error>> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(path, keypair.to_protobuf_encoding()?)?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        fs::set_permissions(path, fs::Permissions::from_mode(0o600))?;
+    }
+    Ok(())
+}
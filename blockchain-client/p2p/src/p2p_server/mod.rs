@@ -1 +1,7 @@
 pub(crate) mod host;
+pub mod protocol;
+pub mod command;
+pub(crate) mod bootstrap;
+pub(crate) mod discovery;
+pub(crate) mod identity;
+pub(crate) mod peers;
@@ -0,0 +1,145 @@
+use std::collections::{BTreeSet, HashMap};
+use std::fs;
+use std::path::{Path, PathBuf};
+use chrono::{DateTime, Utc};
+use libp2p::{identify, Multiaddr, PeerId};
+use crate::config::BANS_FILE;
+use crate::types::dto::{BannedPeer, ConnectedPeer};
+
+#[derive(Default)]
+struct PeerRecord {
+    addresses: BTreeSet<Multiaddr>,
+    protocol_version: Option<String>,
+    agent_version: Option<String>,
+    tip_height: Option<usize>,
+    score: i64,
+    connected: bool,
+}
+
+/// What the node knows about the peers it has talked to, together with the persisted ban list.
+/// Records are kept after a disconnect so that a peer's score survives reconnecting.
+pub(crate) struct PeerBook {
+    peers: HashMap<PeerId, PeerRecord>,
+    bans: HashMap<PeerId, DateTime<Utc>>,
+    bans_file: PathBuf,
+}
+
+impl PeerBook {
+    pub(crate) fn load(data_dir: &Path) -> PeerBook {
+        let bans_file = data_dir.join(BANS_FILE);
+        let bans = match fs::read_to_string(&bans_file) {
+            Ok(json) => serde_json::from_str::<HashMap<String, DateTime<Utc>>>(&json)
+                .unwrap_or_else(|e| {
+                    println!("Ignoring malformed ban list {}: {e}", bans_file.display());
+                    HashMap::new()
+                })
+                .into_iter()
+                .filter_map(|(peer_id, until)| peer_id.parse().ok().map(|peer_id| (peer_id, until)))
+                .collect(),
+            Err(_) => HashMap::new(),
+        };
+        PeerBook {
+            peers: HashMap::new(),
+            bans,
+            bans_file,
+        }
+    }
+
+    pub(crate) fn on_connected(&mut self, peer_id: PeerId, address: Multiaddr) {
+        let record = self.peers.entry(peer_id).or_default();
+        record.connected = true;
+        record.addresses.insert(address);
+    }
+
+    pub(crate) fn on_disconnected(&mut self, peer_id: PeerId) {
+        if let Some(record) = self.peers.get_mut(&peer_id) {
+            record.connected = false;
+            record.addresses.clear();
+        }
+    }
+
+    pub(crate) fn on_identified(&mut self, peer_id: PeerId, info: &identify::Info) {
+        let record = self.peers.entry(peer_id).or_default();
+        record.protocol_version = Some(info.protocol_version.clone());
+        record.agent_version = Some(info.agent_version.clone());
+    }
+
+    pub(crate) fn record_tip(&mut self, peer_id: PeerId, tip_height: usize) {
+        self.peers.entry(peer_id).or_default().tip_height = Some(tip_height);
+    }
+
+    pub(crate) fn adjust_score(&mut self, peer_id: PeerId, delta: i64) {
+        self.peers.entry(peer_id).or_default().score += delta;
+    }
+
+    pub(crate) fn is_connected(&self, peer_id: &PeerId) -> bool {
+        self.peers.get(peer_id).is_some_and(|record| record.connected)
+    }
+
+    pub(crate) fn ban(&mut self, peer_id: PeerId, until: DateTime<Utc>) {
+        self.bans.insert(peer_id, until);
+        self.save_bans();
+    }
+
+    pub(crate) fn unban(&mut self, peer_id: &PeerId) -> bool {
+        let removed = self.bans.remove(peer_id).is_some();
+        if removed {
+            self.save_bans();
+        }
+        removed
+    }
+
+    pub(crate) fn banned_peer_ids(&self) -> Vec<PeerId> {
+        self.bans.keys().copied().collect()
+    }
+
+    /// Removes and returns the bans that ran out before `now`.
+    pub(crate) fn expire_bans(&mut self, now: DateTime<Utc>) -> Vec<PeerId> {
+        let expired: Vec<PeerId> = self.bans.iter()
+            .filter(|(_, until)| **until <= now)
+            .map(|(peer_id, _)| *peer_id)
+            .collect();
+        if !expired.is_empty() {
+            expired.iter().for_each(|peer_id| { self.bans.remove(peer_id); });
+            self.save_bans();
+        }
+        expired
+    }
+
+    /// `topics` looks up the gossipsub topics a peer is subscribed to.
+    pub(crate) fn connected_peers(&self, topics: impl Fn(&PeerId) -> Vec<String>) -> Vec<ConnectedPeer> {
+        self.peers.iter()
+            .filter(|(_, record)| record.connected)
+            .map(|(peer_id, record)| ConnectedPeer {
+                peer_id: peer_id.to_string(),
+                addresses: record.addresses.iter().map(|address| address.to_string()).collect(),
+                protocol_version: record.protocol_version.clone(),
+                agent_version: record.agent_version.clone(),
+                tip_height: record.tip_height,
+                score: record.score,
+                topics: topics(peer_id),
+            })
+            .collect()
+    }
+
+    pub(crate) fn banned_peers(&self) -> Vec<BannedPeer> {
+        self.bans.iter()
+            .map(|(peer_id, until)| BannedPeer {
+                peer_id: peer_id.to_string(),
+                until: *until,
+            })
+            .collect()
+    }
+
+    fn save_bans(&self) {
+        let bans: HashMap<String, DateTime<Utc>> = self.bans.iter()
+            .map(|(peer_id, until)| (peer_id.to_string(), *until))
+            .collect();
+        if let Some(parent) = self.bans_file.parent() {
+            let _ = fs::create_dir_all(parent);
+        }
+        if let Err(e) = fs::write(&self.bans_file, serde_json::to_string_pretty(&bans).unwrap()) {
+            println!("Cannot save ban list {}: {e}", self.bans_file.display());
+        }
+    }
+}
@@ -0,0 +1,83 @@
+use std::error::Error;
+use std::fmt;
+use libp2p::StreamProtocol;
+use serde::{Deserialize, Serialize};
+use architecture::blockchain::block::Block;
+use architecture::wallet::transaction::Transaction;
+
+pub const PROTOCOL_VERSION: u16 = 5;
+
+pub(crate) const KAD_PROTOCOL: StreamProtocol = StreamProtocol::new("/rustchain/kad/1.0.0");
+
+pub(crate) const BLOCKCHAIN_TOPIC: &str = "blockchain";
+pub(crate) const TRANSACTION_POOL_TOPIC: &str = "transaction_pool";
+
+pub(crate) const TOPICS: [&str; 2] = [BLOCKCHAIN_TOPIC, TRANSACTION_POOL_TOPIC];
+
+/// Messages exchanged between the http handlers, the swarm and remote peers.
+/// On the wire every message is prefixed with the protocol version and encoded with bincode.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub enum NetworkMessage {
+    Blockchain(Vec<Block>),
+    Transaction(Transaction),
+}
+
+#[derive(Debug)]
+pub enum ProtocolError {
+    UnsupportedVersion(u16),
+    Encoding(bincode::Error),
+}
+
+/// Advertised through identify so that peers can tell which wire protocol and network we speak.
+pub(crate) fn identify_protocol_version(network_id: &str) -> String {
+    format!("/rustchain/{PROTOCOL_VERSION}/{network_id}")
+}
+
+/// Gossipsub topics are namespaced by the network id so that nodes of different networks never share a mesh.
+pub(crate) fn topic_name(network_id: &str, topic: &str) -> String {
+    format!("{network_id}/{topic}")
+}
+
+impl NetworkMessage {
+    pub fn topic(&self) -> &'static str {
+        match self {
+            NetworkMessage::Blockchain(_) => BLOCKCHAIN_TOPIC,
+            NetworkMessage::Transaction(_) => TRANSACTION_POOL_TOPIC,
+        }
+    }
+
+    pub fn encode(&self) -> Result<Vec<u8>, ProtocolError> {
+        bincode::serialize(&(PROTOCOL_VERSION, self)).map_err(ProtocolError::Encoding)
+    }
+
+    pub fn decode(bytes: &[u8]) -> Result<NetworkMessage, ProtocolError> {
+        // the version is read on its own first so that a newer peer gets a clear error
+        // instead of a garbled payload
+        let version = bincode::deserialize::<u16>(bytes).map_err(ProtocolError::Encoding)?;
+        if version != PROTOCOL_VERSION {
+            return Err(ProtocolError::UnsupportedVersion(version));
+        }
+        let (_, message) = bincode::deserialize::<(u16, NetworkMessage)>(bytes).map_err(ProtocolError::Encoding)?;
+        Ok(message)
+    }
+}
+
+impl fmt::Display for NetworkMessage {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            NetworkMessage::Blockchain(chain) => write!(f, "Blockchain({} blocks)", chain.len()),
+            NetworkMessage::Transaction(transaction) => write!(f, "Transaction({})", transaction.id),
+        }
+    }
+}
+
+impl fmt::Display for ProtocolError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ProtocolError::UnsupportedVersion(version) => write!(f, "unsupported protocol version {version}, expected {PROTOCOL_VERSION}"),
+            ProtocolError::Encoding(e) => write!(f, "malformed message: {e}"),
+        }
+    }
+}
+
+impl Error for ProtocolError {}
@@ -0,0 +1,92 @@
+use std::env;
+use std::fs;
+use chrono::{TimeDelta, Utc};
+use architecture::blockchain::blockchain::Blockchain;
+use architecture::wallet::transaction::Transaction;
+use architecture::wallet::transaction_pool::TransactionPool;
+use architecture::wallet::wallet::Wallet;
+use crate::config::MEMPOOL_FILE;
+use crate::mempool_store;
+use crate::mempool_store::SavedTransaction;
+use crate::{Node, NodeConfig};
+
+#[test]
+fn test_restores_saved_transactions() {
+    let data_dir = env::temp_dir().join(format!("rustchain-mempool-restore-{}", std::process::id()));
+    let blockchain = Blockchain::new();
+    let mut transaction_pool = TransactionPool::new();
+    let transaction = Transaction::new(&Wallet::new(), Wallet::new().public_key, 50).unwrap();
+    transaction_pool.update_or_add_transaction(transaction.clone(), &blockchain).unwrap();
+    mempool_store::save(&data_dir, &transaction_pool, &[]);
+
+    let mut restored_pool = TransactionPool::new();
+    assert!(mempool_store::restore(mempool_store::load(&data_dir), &mut restored_pool, &blockchain).is_empty());
+    assert_eq!(restored_pool.transactions(), vec![transaction.clone()]);
+    assert_eq!(restored_pool.added_at(&transaction.id), transaction_pool.added_at(&transaction.id));
+    let _ = fs::remove_dir_all(&data_dir);
+}
+
+#[test]
+fn test_keeps_saved_transactions_that_were_never_restored() {
+    let data_dir = env::temp_dir().join(format!("rustchain-mempool-unrestored-{}", std::process::id()));
+    let saved = vec![SavedTransaction {
+        transaction: Transaction::new(&Wallet::new(), Wallet::new().public_key, 50).unwrap(),
+        added_at: Utc::now(),
+    }];
+    mempool_store::save(&data_dir, &TransactionPool::new(), &saved);
+
+    assert_eq!(mempool_store::load(&data_dir), saved);
+    let _ = fs::remove_dir_all(&data_dir);
+}
+
+#[test]
+fn test_restored_transactions_expire_from_their_original_admission() {
+    let mut transaction_pool = TransactionPool::new();
+    let transaction = Transaction::new(&Wallet::new(), Wallet::new().public_key, 50).unwrap();
+    let added_at = Utc::now() - TimeDelta::days(30);
+    let saved = vec![SavedTransaction { transaction: transaction.clone(), added_at }];
+
+    assert!(mempool_store::restore(saved, &mut transaction_pool, &Blockchain::new()).is_empty());
+    assert_eq!(transaction_pool.expire(Utc::now()), vec![transaction.id]);
+}
+
+#[test]
+fn test_drops_saved_transactions_that_are_no_longer_valid() {
+    let data_dir = env::temp_dir().join(format!("rustchain-mempool-revalidate-{}", std::process::id()));
+    fs::create_dir_all(&data_dir).unwrap();
+    let valid = Transaction::new(&Wallet::new(), Wallet::new().public_key, 50).unwrap();
+    let mut tampered = Transaction::new(&Wallet::new(), Wallet::new().public_key, 50).unwrap();
+    tampered.outputs[1].amount = 60;
+    let saved = [valid.clone(), tampered].map(|transaction| SavedTransaction { transaction, added_at: Utc::now() });
+    fs::write(data_dir.join(MEMPOOL_FILE), serde_json::to_string(&saved).unwrap()).unwrap();
+
+    let mut transaction_pool = TransactionPool::new();
+    assert_eq!(mempool_store::restore(mempool_store::load(&data_dir), &mut transaction_pool, &Blockchain::new()).len(), 1);
+    assert_eq!(transaction_pool.transactions(), vec![valid]);
+    let _ = fs::remove_dir_all(&data_dir);
+}
+
+#[tokio::test]
+async fn test_restores_against_the_local_tip_at_startup() {
+    let data_dir = env::temp_dir().join(format!("rustchain-mempool-startup-{}", std::process::id()));
+    let valid = Transaction::new(&Wallet::new(), Wallet::new().public_key, 50).unwrap();
+    // spends more than the initial balance, so only a chain paying the sender more can admit it
+    let mut rich_wallet = Wallet::new();
+    rich_wallet.balance = 1000;
+    let unfunded = Transaction::new(&rich_wallet, Wallet::new().public_key, 800).unwrap();
+    let saved = [valid.clone(), unfunded].map(|transaction| SavedTransaction { transaction, added_at: Utc::now() });
+    mempool_store::save(&data_dir, &TransactionPool::new(), &saved);
+
+    let mut config = NodeConfig::new("mempool-startup".to_string());
+    config.data_dir = data_dir.clone();
+    let node = Node::new(config);
+    node.load_mempool().await;
+    assert_eq!(node.transaction_pool.read().await.transactions(), vec![valid]);
+    assert_eq!(*node.saved_transactions.read().await, vec![saved[1].clone()]);
+
+    // a synced chain that still refuses it drops it for good
+    node.restore_mempool(&Blockchain::new()).await;
+    assert!(node.saved_transactions.read().await.is_empty());
+    assert_eq!(node.transaction_pool.read().await.len(), 1);
+    let _ = fs::remove_dir_all(&data_dir);
+}
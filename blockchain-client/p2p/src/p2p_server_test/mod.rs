@@ -0,0 +1,12 @@
+#[cfg(test)]
+mod protocol_test;
+#[cfg(test)]
+mod simulation;
+#[cfg(test)]
+mod network_test;
+#[cfg(test)]
+mod mempool_store_test;
+#[cfg(test)]
+mod wallet_manager_test;
+#[cfg(test)]
+mod node_test;
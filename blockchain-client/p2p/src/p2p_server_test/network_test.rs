@@ -0,0 +1,144 @@
+use architecture::wallet::transaction::Transaction;
+use architecture::wallet::transaction_pool::PoolError;
+use architecture::wallet::wallet::Wallet;
+use chrono::{TimeDelta, Utc};
+use crate::NetworkMessage;
+use crate::mempool_store::SavedTransaction;
+use crate::p2p_server_test::simulation::Simulation;
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn test_mined_block_reaches_every_node() {
+    let simulation = Simulation::new(3);
+    simulation.wait_for_mesh().await;
+
+    simulation.mine(1).await;
+
+    simulation.assert_converged().await;
+    assert_eq!(simulation.tip(0).await.0, 1);
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn test_transaction_reaches_every_mempool() {
+    let simulation = Simulation::new(3);
+    simulation.wait_for_mesh().await;
+
+    let transaction = simulation.submit_transaction(2, &Wallet::new().address(), 50).await;
+
+    simulation.assert_converged().await;
+    assert!(simulation.mempool(0).await.contains(&transaction.id.to_string()));
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn test_pre_signed_transaction_reaches_every_mempool() {
+    let simulation = Simulation::new(3);
+    simulation.wait_for_mesh().await;
+    // the client holds its own keys, the node never sees them
+    let client_wallet = Wallet::new();
+    let transaction = Transaction::new(&client_wallet, Wallet::new().public_key, 50).unwrap();
+
+    let mut tampered = transaction.clone();
+    tampered.outputs[1].amount = 60;
+    assert_eq!(simulation.nodes[1].broadcast_transaction(tampered.clone()).await,
+               Err(PoolError::IdMismatch { expected: tampered.compute_id() }));
+    assert_eq!(simulation.nodes[1].broadcast_transaction(transaction.clone()).await, Ok(transaction.id));
+
+    simulation.assert_converged().await;
+    assert!(simulation.mempool(2).await.contains(&transaction.id.to_string()));
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn test_peer_sending_an_invalid_transaction_is_penalised() {
+    let simulation = Simulation::new(2);
+    simulation.wait_for_mesh().await;
+    let mut tampered = Transaction::new(&Wallet::new(), Wallet::new().public_key, 50).unwrap();
+    tampered.outputs[1].amount = 60;
+
+    simulation.publish(0, NetworkMessage::Transaction(tampered)).await;
+
+    simulation.assert_penalised(1, 0).await;
+    assert!(simulation.mempool(1).await.is_empty());
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn test_peer_sending_an_invalid_chain_is_penalised() {
+    let simulation = Simulation::new(2);
+    simulation.wait_for_mesh().await;
+    let mut blockchain = simulation.nodes[0].blockchain.read().await.clone();
+    blockchain.add_block(vec![]);
+    blockchain.chain[1].data = vec![Transaction::reward_transaction(&Wallet::new(), &Wallet::blockchain_wallet())];
+
+    simulation.publish(0, NetworkMessage::Blockchain(blockchain.chain)).await;
+
+    simulation.assert_penalised(1, 0).await;
+    assert_eq!(simulation.tip(1).await.0, 0);
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn test_partitioned_network_converges_on_the_longest_chain_after_healing() {
+    let mut simulation = Simulation::new(3);
+    simulation.wait_for_mesh().await;
+
+    simulation.partition(&[&[0], &[1, 2]]).await;
+    simulation.mine(0).await;
+    simulation.mine(0).await;
+    simulation.mine(1).await;
+    assert_ne!(simulation.tip(0).await, simulation.tip(1).await);
+
+    simulation.heal().await;
+
+    simulation.assert_converged().await;
+    assert_eq!(simulation.tip(2).await.0, 2);
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn test_transactions_of_the_losing_side_are_pending_again_after_healing() {
+    let mut simulation = Simulation::new(3);
+    simulation.wait_for_mesh().await;
+
+    simulation.partition(&[&[0], &[1, 2]]).await;
+    let transaction = simulation.submit_transaction(0, &Wallet::new().address(), 50).await;
+    simulation.mine(0).await;
+    assert!(simulation.mempool(0).await.is_empty());
+    simulation.mine(1).await;
+    simulation.mine(1).await;
+
+    simulation.heal().await;
+    // gossip drops a chain it already published, so the winning side announces its chain with a new block
+    simulation.mine(1).await;
+
+    simulation.assert_pending(0, &transaction).await;
+    assert_eq!(simulation.tip(0).await, simulation.tip(1).await);
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn test_mined_transactions_leave_every_mempool() {
+    let simulation = Simulation::new(3);
+    simulation.wait_for_mesh().await;
+    let transaction = simulation.submit_transaction(0, &Wallet::new().address(), 50).await;
+    simulation.assert_converged().await;
+
+    simulation.mine(1).await;
+
+    simulation.assert_converged().await;
+    assert!(simulation.mempool(2).await.is_empty());
+    let block = simulation.nodes[2].blockchain.read().await.chain.last().unwrap().clone();
+    assert!(block.data.iter().any(|t| t.id == transaction.id));
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn test_saved_transactions_are_restored_once_the_chain_is_synced() {
+    let simulation = Simulation::new(2);
+    simulation.wait_for_mesh().await;
+    // spends the reward node 0 is about to mine, which the genesis chain alone does not have
+    let mut wallet = simulation.nodes[0].wallet.read().await.clone();
+    wallet.balance = 550;
+    let transaction = Transaction::new(&wallet, Wallet::new().public_key, 520).unwrap();
+    let added_at = Utc::now() - TimeDelta::minutes(5);
+    *simulation.nodes[1].saved_transactions.write().await = vec![SavedTransaction { transaction: transaction.clone(), added_at }];
+
+    simulation.mine(0).await;
+
+    simulation.assert_pending(1, &transaction).await;
+    assert_eq!(simulation.nodes[1].transaction_pool.read().await.added_at(&transaction.id), Some(added_at));
+    assert!(simulation.nodes[1].saved_transactions.read().await.is_empty());
+}
@@ -0,0 +1,23 @@
+use architecture::wallet::hd_wallet::HdWallet;
+use architecture::wallet::transaction::Transaction;
+use architecture::wallet::wallet::Wallet;
+use crate::{Node, NodeConfig};
+
+#[tokio::test]
+async fn test_hd_wallet_node_reports_and_spends_every_address() {
+    let mut hd_wallet = HdWallet::generate();
+    hd_wallet.address_count = 3;
+    let node = Node::with_hd_wallet(NodeConfig::new("hd-wallet".to_string()), hd_wallet.clone());
+    assert_eq!(node.wallet.read().await.public_key, hd_wallet.derive(0).public_key);
+
+    let mut blockchain_wallet = Wallet::blockchain_wallet();
+    blockchain_wallet.chain_id = node.config.chain_id;
+    let reward = Transaction::reward_transaction(&hd_wallet.derive(2), &blockchain_wallet);
+    node.blockchain.write().await.add_block(vec![reward]);
+
+    let balance = node.wallet_balance().await;
+    assert_eq!(balance.confirmed, node.balance(&hd_wallet.derive(2).public_key).await.confirmed);
+    // only the third address received the reward on top of the initial balance
+    let amount = node.balance(&hd_wallet.derive(0).public_key).await.spendable + 1;
+    assert_eq!(node.paying_wallet(amount).await.public_key, hd_wallet.derive(2).public_key);
+}
@@ -0,0 +1,47 @@
+use architecture::blockchain::blockchain::Blockchain;
+use architecture::wallet::transaction::Transaction;
+use architecture::wallet::wallet::Wallet;
+use crate::p2p_server::protocol::{identify_protocol_version, topic_name, NetworkMessage, ProtocolError, PROTOCOL_VERSION, TRANSACTION_POOL_TOPIC};
+
+#[test]
+fn test_encodes_and_decodes_a_blockchain_message() {
+    let mut blockchain = Blockchain::new();
+    blockchain.add_block(vec![Transaction::reward_transaction(&Wallet::new(), &Wallet::blockchain_wallet())]);
+    let message = NetworkMessage::Blockchain(blockchain.chain.clone());
+    let decoded = NetworkMessage::decode(&message.encode().unwrap()).unwrap();
+    assert_eq!(decoded, message);
+}
+
+#[test]
+fn test_encodes_and_decodes_a_transaction_message() {
+    let transaction = Transaction::new(&Wallet::new(), "recipient".to_string(), 50).unwrap();
+    let message = NetworkMessage::Transaction(transaction);
+    let decoded = NetworkMessage::decode(&message.encode().unwrap()).unwrap();
+    assert_eq!(decoded, message);
+    assert_eq!(decoded.topic(), "transaction_pool");
+}
+
+#[test]
+fn test_rejects_an_unsupported_protocol_version() {
+    let transaction = Transaction::new(&Wallet::new(), "recipient".to_string(), 50).unwrap();
+    let mut data = NetworkMessage::Transaction(transaction).encode().unwrap();
+    data[..2].copy_from_slice(&(PROTOCOL_VERSION + 1).to_le_bytes());
+    assert!(matches!(NetworkMessage::decode(&data), Err(ProtocolError::UnsupportedVersion(v)) if v == PROTOCOL_VERSION + 1));
+}
+
+#[test]
+fn test_rejects_a_malformed_message() {
+    let mut data = PROTOCOL_VERSION.to_le_bytes().to_vec();
+    data.extend_from_slice(b"blockchain: []");
+    assert!(matches!(NetworkMessage::decode(&data), Err(ProtocolError::Encoding(_))));
+    assert!(matches!(NetworkMessage::decode(&[]), Err(ProtocolError::Encoding(_))));
+}
+
+#[test]
+fn test_namespaces_topics_and_handshake_by_network() {
+    let network_id = Blockchain::network_id(1);
+    let other_network_id = Blockchain::network_id(2);
+    assert_eq!(topic_name(&network_id, TRANSACTION_POOL_TOPIC), format!("{network_id}/transaction_pool"));
+    assert_ne!(topic_name(&network_id, TRANSACTION_POOL_TOPIC), topic_name(&other_network_id, TRANSACTION_POOL_TOPIC));
+    assert_ne!(identify_protocol_version(&network_id), identify_protocol_version(&other_network_id));
+}
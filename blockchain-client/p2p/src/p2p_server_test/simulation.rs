@@ -0,0 +1,216 @@
+use std::collections::BTreeSet;
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+use chrono::TimeDelta;
+use libp2p::identity::Keypair;
+use libp2p::multiaddr::Protocol;
+use tokio::sync::oneshot;
+use tokio::task::JoinHandle;
+use tokio::time::{sleep, Instant};
+use architecture::wallet::address::Address;
+use architecture::wallet::transaction::Transaction;
+use crate::{Command, Multiaddr, NetworkMessage, Node, NodeConfig, PeerId};
+use crate::p2p_server::host::build_memory_swarm;
+use crate::p2p_server::protocol::{topic_name, TOPICS};
+use crate::types::dto::Peers;
+
+pub(crate) const CONVERGENCE_TIMEOUT: Duration = Duration::from_secs(60);
+const POLL_INTERVAL: Duration = Duration::from_millis(200);
+
+// memory transport ports are global to the process, so every simulated node takes a fresh one
+static NEXT_NODE_ID: AtomicU64 = AtomicU64::new(1);
+
+/// Runs several `Node`s inside the test process, connected over libp2p's memory transport.
+/// Every node bootstraps from all nodes created before it, so the network starts fully connected.
+pub(crate) struct Simulation {
+    pub(crate) nodes: Vec<Node>,
+    addresses: Vec<Multiaddr>,
+    partitions: Option<Vec<Vec<usize>>>,
+    tasks: Vec<JoinHandle<()>>,
+    data_dirs: Vec<PathBuf>,
+}
+
+impl Simulation {
+    pub(crate) fn new(size: usize) -> Simulation {
+        let mut simulation = Simulation {
+            nodes: vec![],
+            addresses: vec![],
+            partitions: None,
+            tasks: vec![],
+            data_dirs: vec![],
+        };
+        for _ in 0..size {
+            let id = NEXT_NODE_ID.fetch_add(1, Ordering::SeqCst);
+            let address = Multiaddr::empty().with(Protocol::Memory(id));
+            let data_dir = env::temp_dir().join(format!("rustchain-simulation-{}-{id}", std::process::id()));
+
+            let mut config = NodeConfig::new(format!("simulation-{id}"));
+            config.listen_addresses = vec![address.clone()];
+            config.bootstrap_peers = simulation.addresses.clone();
+            config.data_dir = data_dir.clone();
+
+            let swarm = build_memory_swarm(Keypair::generate_ed25519(), &config.network_id()).expect("Cannot build memory swarm");
+            let mut node = Node::new(config);
+            let p2p = node.attach(swarm);
+            simulation.tasks.push(tokio::spawn(async move {
+                let _ = p2p.await;
+            }));
+            simulation.nodes.push(node);
+            simulation.addresses.push(address);
+            simulation.data_dirs.push(data_dir);
+        }
+        simulation
+    }
+
+    fn peer_id(&self, index: usize) -> PeerId {
+        self.nodes[index].peer_id.unwrap()
+    }
+
+    fn reachable(&self, from: usize, to: usize) -> bool {
+        match &self.partitions {
+            Some(groups) => groups.iter().any(|group| group.contains(&from) && group.contains(&to)),
+            None => true,
+        }
+    }
+
+    async fn command<T>(&self, index: usize, command: impl FnOnce(oneshot::Sender<T>) -> Command) -> T {
+        let (reply, response) = oneshot::channel();
+        self.nodes[index].command_sender.as_ref().unwrap().send(command(reply)).await
+            .expect("Simulated swarm has stopped");
+        response.await.expect("Simulated swarm dropped the request")
+    }
+
+    pub(crate) async fn peers(&self, index: usize) -> Peers {
+        self.command(index, Command::ListPeers).await
+    }
+
+    /// Waits until every node is connected to every node it can reach and sees it subscribed to all topics,
+    /// so that anything published afterwards reaches the whole (partitioned) network.
+    pub(crate) async fn wait_for_mesh(&self) {
+        let deadline = Instant::now() + CONVERGENCE_TIMEOUT;
+        let network_id = self.nodes[0].config.network_id();
+        'poll: loop {
+            for i in 0..self.nodes.len() {
+                let peers = self.peers(i).await;
+                for j in (0..self.nodes.len()).filter(|j| *j != i && self.reachable(i, *j)) {
+                    let peer_id = self.peer_id(j).to_string();
+                    let meshed = peers.connected.iter().any(|peer| peer.peer_id == peer_id
+                        && TOPICS.iter().all(|topic| peer.topics.contains(&topic_name(&network_id, topic))));
+                    if !meshed {
+                        assert!(Instant::now() < deadline, "Node {i} did not connect to node {j} within {CONVERGENCE_TIMEOUT:?}");
+                        sleep(POLL_INTERVAL).await;
+                        continue 'poll;
+                    }
+                }
+            }
+            return;
+        }
+    }
+
+    /// Splits the network into groups that cannot talk to each other, by having nodes ban every node
+    /// outside of their own group.
+    pub(crate) async fn partition(&mut self, groups: &[&[usize]]) {
+        self.partitions = Some(groups.iter().map(|group| group.to_vec()).collect());
+        for i in 0..self.nodes.len() {
+            for j in (0..self.nodes.len()).filter(|j| !self.reachable(i, *j)) {
+                let peer_id = self.peer_id(j);
+                self.command(i, |reply| Command::Ban(peer_id, TimeDelta::days(1), reply)).await;
+            }
+        }
+        self.wait_for_mesh().await;
+    }
+
+    pub(crate) async fn heal(&mut self) {
+        for i in 0..self.nodes.len() {
+            for j in (0..self.nodes.len()).filter(|j| !self.reachable(i, *j)) {
+                let peer_id = self.peer_id(j);
+                self.command(i, |reply| Command::Unban(peer_id, reply)).await;
+            }
+        }
+        self.partitions = None;
+        for i in 0..self.nodes.len() {
+            for j in (0..i).rev() {
+                self.nodes[i].command_sender.as_ref().unwrap().send(Command::Dial(self.addresses[j].clone())).await
+                    .expect("Simulated swarm has stopped");
+            }
+        }
+        self.wait_for_mesh().await;
+    }
+
+    pub(crate) async fn mine(&self, index: usize) {
+        self.nodes[index].clone().mine().await.expect("Failed to mine block");
+    }
+
+    pub(crate) async fn submit_transaction(&self, index: usize, recipient: &Address, amount: u64) -> Transaction {
+        self.nodes[index].submit_transaction(recipient, amount).await.expect("Failed to create transaction")
+    }
+
+    pub(crate) async fn tip(&self, index: usize) -> (usize, String) {
+        let blockchain = self.nodes[index].blockchain.read().await;
+        (blockchain.chain.len() - 1, blockchain.chain.last().unwrap().hash.clone())
+    }
+
+    pub(crate) async fn mempool(&self, index: usize) -> BTreeSet<String> {
+        self.nodes[index].transaction_pool.read().await.transactions().iter()
+            .map(|transaction| transaction.id.to_string())
+            .collect()
+    }
+
+    /// Waits until the transaction is pending on the given node.
+    pub(crate) async fn assert_pending(&self, index: usize, transaction: &Transaction) {
+        let deadline = Instant::now() + CONVERGENCE_TIMEOUT;
+        while !self.mempool(index).await.contains(&transaction.id.to_string()) {
+            assert!(Instant::now() < deadline, "Transaction {} did not reach node {index} within {CONVERGENCE_TIMEOUT:?}", transaction.id);
+            sleep(POLL_INTERVAL).await;
+        }
+    }
+
+    /// Gossips a message from the given node as is, without applying it to the node itself.
+    pub(crate) async fn publish(&self, index: usize, message: NetworkMessage) {
+        self.nodes[index].event_sender.as_ref().unwrap().send(message).await.expect("Simulated swarm has stopped");
+    }
+
+    /// Waits until node `index` scores node `peer` below zero for the messages it sent.
+    pub(crate) async fn assert_penalised(&self, index: usize, peer: usize) {
+        let deadline = Instant::now() + CONVERGENCE_TIMEOUT;
+        let peer_id = self.peer_id(peer).to_string();
+        loop {
+            let score = self.peers(index).await.connected.into_iter()
+                .find(|connected| connected.peer_id == peer_id)
+                .map(|connected| connected.score);
+            if score.is_some_and(|score| score < 0) {
+                return;
+            }
+            assert!(Instant::now() < deadline, "Node {index} did not penalise node {peer} within {CONVERGENCE_TIMEOUT:?}, score: {score:?}");
+            sleep(POLL_INTERVAL).await;
+        }
+    }
+
+    /// Waits until all nodes agree on the chain tip and on the content of their transaction pools.
+    pub(crate) async fn assert_converged(&self) {
+        let deadline = Instant::now() + CONVERGENCE_TIMEOUT;
+        loop {
+            let mut tips = vec![];
+            let mut mempools = vec![];
+            for i in 0..self.nodes.len() {
+                tips.push(self.tip(i).await);
+                mempools.push(self.mempool(i).await);
+            }
+            if tips.windows(2).all(|w| w[0] == w[1]) && mempools.windows(2).all(|w| w[0] == w[1]) {
+                return;
+            }
+            assert!(Instant::now() < deadline, "Nodes did not converge within {CONVERGENCE_TIMEOUT:?}\ntips: {tips:?}\nmempools: {mempools:?}");
+            sleep(POLL_INTERVAL).await;
+        }
+    }
+}
+
+impl Drop for Simulation {
+    fn drop(&mut self) {
+        self.tasks.iter().for_each(|task| task.abort());
+        self.data_dirs.iter().for_each(|data_dir| { let _ = fs::remove_dir_all(data_dir); });
+    }
+}
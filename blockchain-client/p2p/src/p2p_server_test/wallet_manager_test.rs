@@ -0,0 +1,73 @@
+use std::env;
+use std::fs;
+use architecture::wallet::address::AddressError;
+use architecture::wallet::keystore::KeystoreError;
+use architecture::wallet::wallet::Wallet;
+use crate::wallet_manager::{WalletError, WalletManager};
+
+fn wallet_manager(test: &str) -> WalletManager {
+    let wallet_dir = env::temp_dir().join(format!("rustchain-wallets-{test}-{}", std::process::id()));
+    let _ = fs::remove_dir_all(&wallet_dir);
+    WalletManager::new(wallet_dir, 7)
+}
+
+#[test]
+fn test_creates_unloads_and_loads_named_wallets() {
+    let mut wallets = wallet_manager("lifecycle");
+    let public_key = wallets.create("payments", "password").unwrap().public_key.clone();
+    assert_eq!(wallets.get("payments").unwrap().chain_id, 7);
+    assert_eq!(wallets.create("payments", "password").err(), Some(WalletError::AlreadyExists { name: "payments".to_string() }));
+
+    wallets.unload("payments").unwrap();
+    assert_eq!(wallets.get("payments").err(), Some(WalletError::NotLoaded { name: "payments".to_string() }));
+    assert_eq!(wallets.unload("payments"), Err(WalletError::NotLoaded { name: "payments".to_string() }));
+
+    assert_eq!(wallets.load("payments", "wrong").err(), Some(WalletError::Keystore { reason: KeystoreError::WrongPassword }));
+    assert_eq!(wallets.load("payments", "password").unwrap().public_key, public_key);
+    assert_eq!(wallets.load("payments", "password").err(), Some(WalletError::AlreadyLoaded { name: "payments".to_string() }));
+}
+
+#[test]
+fn test_keeps_wallets_apart() {
+    let mut wallets = wallet_manager("names");
+    wallets.create("a", "password").unwrap();
+    wallets.create("b", "password").unwrap();
+    assert_ne!(wallets.get("a").unwrap().public_key, wallets.get("b").unwrap().public_key);
+    assert_eq!(wallets.wallets().map(|(name, _)| name.as_str()).collect::<Vec<_>>(), vec!["a", "b"]);
+    assert_eq!(wallets.load("missing", "password").err(), Some(WalletError::NotFound { name: "missing".to_string() }));
+}
+
+#[test]
+fn test_rejects_names_that_are_not_plain_file_names() {
+    let mut wallets = wallet_manager("invalid");
+    for name in ["", "../escape", "a/b", "with space"] {
+        assert_eq!(wallets.create(name, "password").err(), Some(WalletError::InvalidName { name: name.to_string() }));
+    }
+}
+
+#[test]
+fn test_watch_only_wallets_are_persisted_but_cannot_spend() {
+    let mut wallets = wallet_manager("watch-only");
+    let mut cold = Wallet::new();
+    cold.chain_id = 7;
+    let addresses = vec![cold.address().to_string()];
+    assert_eq!(wallets.import_watch_only("cold", &addresses).unwrap().addresses(), &[cold.address()]);
+    assert_eq!(wallets.get("cold").err(), Some(WalletError::WatchOnly { name: "cold".to_string() }));
+    assert_eq!(wallets.create("cold", "password").err(), Some(WalletError::AlreadyExists { name: "cold".to_string() }));
+
+    wallets.unload("cold").unwrap();
+    assert!(wallets.is_watch_only("cold"));
+    assert_eq!(wallets.load_watch_only("cold").unwrap().addresses(), &[cold.address()]);
+    assert_eq!(wallets.watch_only_wallets().count(), 1);
+}
+
+#[test]
+fn test_rejects_watch_only_wallets_without_valid_addresses() {
+    let mut wallets = wallet_manager("watch-invalid");
+    assert_eq!(wallets.import_watch_only("empty", &[]).err(), Some(WalletError::NoAddresses));
+    // addresses of another chain are refused like any mistyped one
+    let address = Wallet::new().address().to_string();
+    assert!(matches!(wallets.import_watch_only("other", &[address]).err(),
+                     Some(WalletError::InvalidAddress { reason: AddressError::WrongNetwork { .. }, .. })));
+    assert!(!wallets.is_watch_only("other"));
+}
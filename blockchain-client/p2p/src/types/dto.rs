@@ -1,24 +1,170 @@
+use std::collections::BTreeMap;
+use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
+use architecture::wallet::address::{Address, AddressError};
+use architecture::wallet::transaction::{Transaction, TransactionId};
+use architecture::wallet::transaction_pool::PoolError;
+use crate::wallet_manager::WalletError;
 
 
 #[derive(Serialize, Deserialize)]
-pub struct BlockchainData {
-    pub data: String,
+pub struct TransactionData {
+    pub recipient: String,
+    pub amount: u64,
 }
 
-
 #[derive(Serialize, Deserialize)]
-pub struct TransactionData {
+pub struct UnsignedTransactionData {
+    pub sender: String,
     pub recipient: String,
     pub amount: u64,
 }
 
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, Debug)]
 pub struct Balance{
-    pub balance: u64,
+    pub confirmed: u64,
+    pub spendable: u64,
+    pub pending_incoming: u64,
+    pub pending_outgoing: u64,
+    /// Confirmations funds need before they are spendable.
+    pub confirmations: usize,
 }
 
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize)]
 pub struct PublicKey{
     pub public_key: String,
-}
\ No newline at end of file
+    pub address: Address,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct PeerAddress{
+    pub address: String,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct ErrorMessage{
+    pub error: String,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct KnownPeer{
+    pub peer_id: String,
+    pub addresses: Vec<String>,
+    pub connected: bool,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct NetworkInfo{
+    pub routing_table_size: usize,
+    pub connected_peers: usize,
+    pub known_peers: Vec<KnownPeer>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct NodeStatus{
+    pub peer_id: String,
+    pub http_port: String,
+    pub chain_id: u32,
+    pub network_id: String,
+    pub chain_height: usize,
+    pub tip_hash: String,
+    pub pending_transactions: usize,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ConnectedPeer{
+    pub peer_id: String,
+    pub addresses: Vec<String>,
+    pub protocol_version: Option<String>,
+    pub agent_version: Option<String>,
+    pub tip_height: Option<usize>,
+    pub score: i64,
+    pub topics: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct BannedPeer{
+    pub peer_id: String,
+    pub until: DateTime<Utc>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Peers{
+    pub connected: Vec<ConnectedPeer>,
+    pub banned: Vec<BannedPeer>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct BanRequest{
+    pub duration_secs: Option<u64>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct SubmittedTransaction{
+    pub id: TransactionId,
+}
+
+#[derive(Serialize, Debug)]
+pub struct RejectedTransaction{
+    pub error: String,
+    pub reason: PoolError,
+}
+
+
+#[derive(Serialize, Deserialize)]
+pub struct CreateWallet{
+    pub name: String,
+    pub password: String,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct LoadWallet{
+    /// Not needed for watch-only wallets.
+    #[serde(default)]
+    pub password: String,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct ImportWatchOnly{
+    pub name: String,
+    pub addresses: Vec<String>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct WalletInfo{
+    pub name: String,
+    pub public_key: String,
+    pub address: Address,
+}
+
+#[derive(Serialize, Debug)]
+pub struct WatchOnlyInfo{
+    pub name: String,
+    pub addresses: Vec<Address>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct WatchOnlyBalance{
+    #[serde(flatten)]
+    pub balance: Balance,
+    /// Confirmed balance of every watched address.
+    pub addresses: BTreeMap<String, u64>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct WalletTransactions{
+    pub confirmed: Vec<Transaction>,
+    pub pending: Vec<Transaction>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct RejectedWalletRequest{
+    pub error: String,
+    pub reason: WalletError,
+}
+
+#[derive(Serialize, Debug)]
+pub struct RejectedAddress{
+    pub error: String,
+    pub reason: AddressError,
+}
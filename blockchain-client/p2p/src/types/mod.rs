@@ -1 +1 @@
-pub(crate) mod dto;
\ No newline at end of file
+pub mod dto;
\ No newline at end of file
@@ -0,0 +1,208 @@
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use architecture::wallet::address::{Address, AddressError};
+use architecture::wallet::keystore::{Keystore, KeystoreError};
+use architecture::wallet::wallet::Wallet;
+use architecture::wallet::watch_only::WatchOnlyWallet;
+use serde::{Deserialize, Serialize};
+
+const MAX_NAME_LEN: usize = 64;
+
+/// Named wallets a node holds besides its own mining wallet, so several services can share one node.
+/// Every wallet lives in a password-encrypted keystore `<name>.json` in the wallet directory; loading
+/// decrypts it into memory and unloading forgets the key again, leaving the keystore in place.
+/// Watch-only wallets share the same names and live unencrypted in `<name>.watch.json`, as they hold no key.
+pub struct WalletManager {
+    wallet_dir: PathBuf,
+    chain_id: u32,
+    wallets: BTreeMap<String, Wallet>,
+    watch_only: BTreeMap<String, WatchOnlyWallet>,
+}
+
+/// On disk form of a watch-only wallet.
+#[derive(Serialize, Deserialize)]
+struct WatchList {
+    addresses: Vec<String>,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+#[serde(tag = "code", rename_all = "snake_case")]
+pub enum WalletError {
+    InvalidName { name: String },
+    AlreadyExists { name: String },
+    AlreadyLoaded { name: String },
+    NotFound { name: String },
+    NotLoaded { name: String },
+    WatchOnly { name: String },
+    NoAddresses,
+    InvalidAddress { address: String, reason: AddressError },
+    Io { message: String },
+    Keystore { reason: KeystoreError },
+}
+
+impl WalletManager {
+    pub fn new(wallet_dir: PathBuf, chain_id: u32) -> WalletManager {
+        WalletManager {
+            wallet_dir,
+            chain_id,
+            wallets: BTreeMap::new(),
+            watch_only: BTreeMap::new(),
+        }
+    }
+
+    /// Generates a wallet, saves its keystore and loads it.
+    pub fn create(&mut self, name: &str, password: &str) -> Result<&Wallet, WalletError> {
+        self.check_unused(name)?;
+        let path = self.keystore_path(name)?;
+        let wallet = Wallet::new();
+        Keystore::encrypt(&wallet, password).save(&path).map_err(|reason| WalletError::Keystore { reason })?;
+        Ok(self.insert(name, wallet))
+    }
+
+    /// Watches the given addresses under `name`, saves the list and loads it. Nothing secret is involved, so no password either.
+    pub fn import_watch_only(&mut self, name: &str, addresses: &[String]) -> Result<&WatchOnlyWallet, WalletError> {
+        self.check_unused(name)?;
+        let path = self.watch_list_path(name)?;
+        if addresses.is_empty() {
+            return Err(WalletError::NoAddresses);
+        }
+        let wallet = self.watch_only_wallet(addresses)?;
+        let watch_list = WatchList { addresses: wallet.addresses().iter().map(Address::to_string).collect() };
+        fs::create_dir_all(&self.wallet_dir).map_err(|e| WalletError::io(&self.wallet_dir, e))?;
+        fs::write(&path, serde_json::to_string_pretty(&watch_list).unwrap()).map_err(|e| WalletError::io(&path, e))?;
+        self.watch_only.insert(name.to_string(), wallet);
+        Ok(&self.watch_only[name])
+    }
+
+    pub fn load(&mut self, name: &str, password: &str) -> Result<&Wallet, WalletError> {
+        let path = self.keystore_path(name)?;
+        if self.is_loaded(name) {
+            return Err(WalletError::AlreadyLoaded { name: name.to_string() });
+        }
+        if !path.exists() {
+            return Err(WalletError::NotFound { name: name.to_string() });
+        }
+        let wallet = Keystore::load(&path).and_then(|keystore| keystore.decrypt(password))
+            .map_err(|reason| WalletError::Keystore { reason })?;
+        Ok(self.insert(name, wallet))
+    }
+
+    pub fn load_watch_only(&mut self, name: &str) -> Result<&WatchOnlyWallet, WalletError> {
+        let path = self.watch_list_path(name)?;
+        if self.is_loaded(name) {
+            return Err(WalletError::AlreadyLoaded { name: name.to_string() });
+        }
+        if !path.exists() {
+            return Err(WalletError::NotFound { name: name.to_string() });
+        }
+        let json = fs::read_to_string(&path).map_err(|e| WalletError::io(&path, e))?;
+        let watch_list: WatchList = serde_json::from_str(&json)
+            .map_err(|e| WalletError::Io { message: format!("{}: {e}", path.display()) })?;
+        let wallet = self.watch_only_wallet(&watch_list.addresses)?;
+        self.watch_only.insert(name.to_string(), wallet);
+        Ok(&self.watch_only[name])
+    }
+
+    /// Whether `name` is saved as a watch-only wallet rather than a keystore.
+    pub fn is_watch_only(&self, name: &str) -> bool {
+        self.watch_list_path(name).is_ok_and(|path| path.exists())
+    }
+
+    pub fn unload(&mut self, name: &str) -> Result<(), WalletError> {
+        let unloaded = self.wallets.remove(name).is_some() || self.watch_only.remove(name).is_some();
+        if !unloaded {
+            return Err(WalletError::NotLoaded { name: name.to_string() });
+        }
+        Ok(())
+    }
+
+    /// A wallet that can sign. Asking for a watch-only one is an error, as it has no key to spend with.
+    pub fn get(&self, name: &str) -> Result<&Wallet, WalletError> {
+        if self.watch_only.contains_key(name) {
+            return Err(WalletError::WatchOnly { name: name.to_string() });
+        }
+        self.wallets.get(name).ok_or_else(|| WalletError::NotLoaded { name: name.to_string() })
+    }
+
+    pub fn get_watch_only_mut(&mut self, name: &str) -> Result<&mut WatchOnlyWallet, WalletError> {
+        self.watch_only.get_mut(name).ok_or_else(|| WalletError::NotLoaded { name: name.to_string() })
+    }
+
+    /// Loaded wallets by name.
+    pub fn wallets(&self) -> impl Iterator<Item = (&String, &Wallet)> {
+        self.wallets.iter()
+    }
+
+    /// Loaded watch-only wallets by name.
+    pub fn watch_only_wallets(&self) -> impl Iterator<Item = (&String, &WatchOnlyWallet)> {
+        self.watch_only.iter()
+    }
+
+    fn is_loaded(&self, name: &str) -> bool {
+        self.wallets.contains_key(name) || self.watch_only.contains_key(name)
+    }
+
+    /// A name belongs to either a keystore or a watch list, never both.
+    fn check_unused(&self, name: &str) -> Result<(), WalletError> {
+        if self.keystore_path(name)?.exists() || self.watch_list_path(name)?.exists() {
+            return Err(WalletError::AlreadyExists { name: name.to_string() });
+        }
+        Ok(())
+    }
+
+    fn watch_only_wallet(&self, addresses: &[String]) -> Result<WatchOnlyWallet, WalletError> {
+        let addresses = addresses.iter()
+            .map(|address| Address::parse(address, self.chain_id)
+                .map_err(|reason| WalletError::InvalidAddress { address: address.clone(), reason }))
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(WatchOnlyWallet::new(addresses))
+    }
+
+    fn insert(&mut self, name: &str, mut wallet: Wallet) -> &Wallet {
+        wallet.chain_id = self.chain_id;
+        self.wallets.insert(name.to_string(), wallet);
+        &self.wallets[name]
+    }
+
+    /// Names end up in file paths, so only plain names are allowed.
+    fn keystore_path(&self, name: &str) -> Result<PathBuf, WalletError> {
+        let valid = !name.is_empty() && name.len() <= MAX_NAME_LEN
+            && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+        if !valid {
+            return Err(WalletError::InvalidName { name: name.to_string() });
+        }
+        Ok(self.wallet_dir.join(format!("{name}.json")))
+    }
+
+    fn watch_list_path(&self, name: &str) -> Result<PathBuf, WalletError> {
+        self.keystore_path(name).map(|path| path.with_extension("watch.json"))
+    }
+}
+
+impl WalletError {
+    fn io(path: &Path, e: std::io::Error) -> WalletError {
+        WalletError::Io { message: format!("{}: {e}", path.display()) }
+    }
+}
+
+impl fmt::Display for WalletError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            WalletError::InvalidName { name } => write!(f, "'{name}' is not a valid wallet name, use up to {MAX_NAME_LEN} letters, digits, '-' or '_'"),
+            WalletError::AlreadyExists { name } => write!(f, "wallet {name} already exists"),
+            WalletError::AlreadyLoaded { name } => write!(f, "wallet {name} is already loaded"),
+            WalletError::NotFound { name } => write!(f, "wallet {name} does not exist"),
+            WalletError::NotLoaded { name } => write!(f, "wallet {name} is not loaded"),
+            WalletError::WatchOnly { name } => write!(f, "wallet {name} is watch-only and cannot sign, build an unsigned transaction and sign it where the key is kept"),
+            WalletError::NoAddresses => write!(f, "a watch-only wallet needs at least one address"),
+            WalletError::InvalidAddress { address, reason } => write!(f, "cannot watch '{address}': {reason}"),
+            WalletError::Io { message } => write!(f, "cannot access watch list {message}"),
+            WalletError::Keystore { reason } => write!(f, "{reason}"),
+        }
+    }
+}
+
+impl Error for WalletError {}
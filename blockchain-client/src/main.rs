@@ -1,5 +1,13 @@
-use clap::{Arg, Command};
-use p2p::Node;
+use std::fs;
+use std::path::{Path, PathBuf};
+use architecture::blockchain::blockchain::Blockchain;
+use architecture::wallet::address::Address;
+use architecture::wallet::hd_wallet::HdWallet;
+use architecture::wallet::keystore::{Keystore, KeystoreError, KeystoreKind};
+use architecture::wallet::transaction::UnsignedTransaction;
+use architecture::wallet::wallet::Wallet;
+use clap::{Arg, ArgAction, ArgMatches, Command};
+use p2p::{Multiaddr, Node, NodeConfig};
 
 #[tokio::main]
 async fn main() {
@@ -8,15 +16,265 @@ async fn main() {
             .long("http_port")
             .value_name("PORT")
             .help("Sets a custom http port"))
+        .arg(Arg::new("listen")
+            .long("listen")
+            .value_name("MULTIADDR")
+            .action(ArgAction::Append)
+            .help("Listens for peers on a fixed multiaddr, can be repeated"))
+        .arg(Arg::new("bootstrap")
+            .long("bootstrap")
+            .value_name("MULTIADDR")
+            .action(ArgAction::Append)
+            .help("Dials a bootstrap peer at startup and redials it on disconnect, can be repeated"))
+        .arg(Arg::new("data_dir")
+            .long("data_dir")
+            .value_name("DIR")
+            .help("Directory for the node key and other node state, defaults to data/node-<http_port>"))
+        .arg(Arg::new("node_key_file")
+            .long("node_key_file")
+            .value_name("FILE")
+            .help("Loads the libp2p identity from a file instead of the data directory"))
+        .arg(Arg::new("chain_id")
+            .long("chain_id")
+            .value_name("ID")
+            .value_parser(clap::value_parser!(u32))
+            .help("Joins the network of the given chain id, nodes with different chain ids ignore each other"))
+        .arg(Arg::new("confirmations")
+            .long("confirmations")
+            .value_name("BLOCKS")
+            .value_parser(clap::value_parser!(u64).range(1..))
+            .help("Confirmations received funds need before the node's wallets can spend them, defaults to 1"))
+        .arg(Arg::new("wallet")
+            .long("wallet")
+            .value_name("FILE")
+            .env("RUSTCHAIN_WALLET")
+            .global(true)
+            .help("Wallet keystore, defaults to wallet.json in the data directory"))
+        .arg(Arg::new("wallet_password_file")
+            .long("wallet_password_file")
+            .value_name("FILE")
+            .global(true)
+            .help("Reads the keystore password from a file instead of RUSTCHAIN_WALLET_PASSWORD"))
+        .subcommand(Command::new("wallet")
+            .about("Manages the wallet keystore without starting a node")
+            .subcommand_required(true)
+            .subcommand(Command::new("import")
+                .about("Creates a keystore from a hex encoded secret key")
+                .arg(Arg::new("key_file")
+                    .long("key_file")
+                    .value_name("FILE")
+                    .required(true)
+                    .help("File holding the hex encoded secp256k1 secret key")))
+            .subcommand(Command::new("generate")
+                .about("Creates an HD wallet keystore and prints its backup phrase"))
+            .subcommand(Command::new("recover")
+                .about("Creates an HD wallet keystore from a backup phrase")
+                .arg(Arg::new("phrase_file")
+                    .long("phrase_file")
+                    .value_name("FILE")
+                    .required(true)
+                    .help("File holding the 24 word BIP-39 phrase"))
+                .arg(Arg::new("passphrase_file")
+                    .long("passphrase_file")
+                    .value_name("FILE")
+                    .help("File holding the optional BIP-39 passphrase the wallet was created with"))
+                .arg(Arg::new("chain_file")
+                    .long("chain_file")
+                    .value_name("FILE")
+                    .help("Chain JSON as returned by GET /blockchain, scanned for every address the wallet used")))
+            .subcommand(Command::new("phrase")
+                .about("Prints the backup phrase of an HD wallet keystore"))
+            .subcommand(Command::new("export")
+                .about("Prints the hex encoded secret key of the keystore"))
+            .subcommand(Command::new("sign")
+                .about("Signs an unsigned transaction built by a node, works offline")
+                .arg(Arg::new("unsigned")
+                    .long("unsigned")
+                    .value_name("FILE")
+                    .required(true)
+                    .help("Unsigned transaction JSON as returned by POST /transactions/unsigned"))))
         .get_matches();
-    
+
+    let password = match wallet_password(&matches) {
+        Ok(password) => password,
+        Err(e) => {
+            println!("{e}");
+            return;
+        }
+    };
+    if let Some(("wallet", wallet_matches)) = matches.subcommand() {
+        let Some(wallet_file) = matches.get_one::<String>("wallet") else {
+            println!("Please provide --wallet");
+            return;
+        };
+        let Some(password) = password else {
+            println!("Please provide the keystore password in RUSTCHAIN_WALLET_PASSWORD or --wallet_password_file");
+            return;
+        };
+        if let Err(e) = run_wallet_command(wallet_matches, Path::new(wallet_file), &password) {
+            println!("{e}");
+        }
+        return;
+    }
+
     let http_port = matches.get_one::<String>("http_port");
-    
+
     if http_port.is_none() {
         println!("Please provide http_port");
         return;
     }
 
-    let node = Node::new(http_port.unwrap().to_string());
+    let mut config = NodeConfig::new(http_port.unwrap().to_string());
+    if let Some(addresses) = matches.get_many::<String>("listen") {
+        match parse_multiaddrs(addresses) {
+            Ok(addresses) => config.listen_addresses = addresses,
+            Err(e) => {
+                println!("{e}");
+                return;
+            }
+        }
+    }
+    if let Some(addresses) = matches.get_many::<String>("bootstrap") {
+        match parse_multiaddrs(addresses) {
+            Ok(addresses) => config.bootstrap_peers = addresses,
+            Err(e) => {
+                println!("{e}");
+                return;
+            }
+        }
+    }
+
+    if let Some(data_dir) = matches.get_one::<String>("data_dir") {
+        config.data_dir = PathBuf::from(data_dir);
+    }
+    config.node_key_file = matches.get_one::<String>("node_key_file").map(PathBuf::from);
+    if let Some(chain_id) = matches.get_one::<u32>("chain_id") {
+        config.chain_id = *chain_id;
+    }
+    if let Some(confirmations) = matches.get_one::<u64>("confirmations") {
+        config.confirmations = *confirmations as usize;
+    }
+    config.wallet_file = matches.get_one::<String>("wallet").map(PathBuf::from);
+
+    let node = match password {
+        Some(password) => match open_node(config, &password) {
+            Ok(node) => node,
+            Err(e) => {
+                println!("{e}");
+                return;
+            }
+        },
+        None if config.wallet_file.is_some() => {
+            println!("Please provide the keystore password in RUSTCHAIN_WALLET_PASSWORD or --wallet_password_file");
+            return;
+        }
+        None => {
+            println!("No wallet password given, mining to a throwaway wallet");
+            Node::new(config)
+        }
+    };
+    println!("Wallet address: {}", Address::from_public_key(&node.wallet.read().await.verifying_key, node.config.chain_id));
+    if let Some(hd_wallet) = &node.hd_wallet {
+        println!("HD wallet with {} addresses", hd_wallet.address_count);
+    }
+
     node.start().await.expect("Cannot start node");
 }
+
+/// Opens the node's keystore, creating a single key one on first run.
+fn open_node(config: NodeConfig, password: &str) -> Result<Node, KeystoreError> {
+    let wallet_file = config.wallet_file();
+    if wallet_file.exists() {
+        let keystore = Keystore::load(&wallet_file)?;
+        if keystore.kind == KeystoreKind::HdSeed {
+            return Ok(Node::with_hd_wallet(config, keystore.decrypt_hd(password)?));
+        }
+    }
+    let wallet = Keystore::load_or_create(&wallet_file, password)?;
+    Ok(Node::with_wallet(config, wallet))
+}
+
+fn parse_multiaddrs<'a>(addresses: impl Iterator<Item = &'a String>) -> Result<Vec<Multiaddr>, String> {
+    addresses
+        .map(|address| address.parse::<Multiaddr>().map_err(|e| format!("Invalid multiaddr '{address}': {e}")))
+        .collect()
+}
+
+/// The keystore password, from a file or the environment. Never taken as a flag so it stays out of shell history.
+fn wallet_password(matches: &ArgMatches) -> Result<Option<String>, String> {
+    if let Some(password_file) = matches.get_one::<String>("wallet_password_file") {
+        let password = fs::read_to_string(password_file).map_err(|e| format!("Cannot read {password_file}: {e}"))?;
+        return Ok(Some(password.trim_end_matches(['\r', '\n']).to_string()));
+    }
+    Ok(std::env::var("RUSTCHAIN_WALLET_PASSWORD").ok())
+}
+
+fn run_wallet_command(matches: &ArgMatches, wallet_file: &Path, password: &str) -> Result<(), Box<dyn std::error::Error>> {
+    match matches.subcommand() {
+        Some(("import", import_matches)) => {
+            let key_file = import_matches.get_one::<String>("key_file").unwrap();
+            let secret_key = fs::read_to_string(key_file).map_err(|e| format!("Cannot read {key_file}: {e}"))?;
+            let wallet = Wallet::from_secret_key_hex(&secret_key)?;
+            Keystore::encrypt(&wallet, password).save(wallet_file)?;
+            println!("Imported wallet {} into {}", wallet.public_key, wallet_file.display());
+        }
+        Some(("generate", _)) => {
+            let hd_wallet = HdWallet::generate();
+            Keystore::encrypt_hd(&hd_wallet, password).save(wallet_file)?;
+            println!("Generated HD wallet {} in {}", hd_wallet.derive(0).public_key, wallet_file.display());
+            eprintln!("Write down the backup phrase, it restores every address of the wallet:");
+            println!("{}", hd_wallet.phrase());
+        }
+        Some(("recover", recover_matches)) => {
+            let phrase_file = recover_matches.get_one::<String>("phrase_file").unwrap();
+            let phrase = fs::read_to_string(phrase_file).map_err(|e| format!("Cannot read {phrase_file}: {e}"))?;
+            let passphrase = match recover_matches.get_one::<String>("passphrase_file") {
+                Some(passphrase_file) => fs::read_to_string(passphrase_file).map_err(|e| format!("Cannot read {passphrase_file}: {e}"))?
+                    .trim_end_matches(['\r', '\n']).to_string(),
+                None => String::new(),
+            };
+            let blockchain = match recover_matches.get_one::<String>("chain_file") {
+                Some(chain_file) => {
+                    let json = fs::read_to_string(chain_file).map_err(|e| format!("Cannot read {chain_file}: {e}"))?;
+                    let blockchain: Blockchain = serde_json::from_str(&json)?;
+                    if blockchain.chain.is_empty() || !Blockchain::is_valid_chain(&blockchain.chain) {
+                        return Err(format!("{chain_file} does not hold a valid chain").into());
+                    }
+                    blockchain
+                }
+                None => {
+                    eprintln!("No --chain_file given, the node will only see the first address");
+                    Blockchain::new()
+                }
+            };
+            let hd_wallet = HdWallet::recover(&phrase, &passphrase, &blockchain)?;
+            Keystore::encrypt_hd(&hd_wallet, password).save(wallet_file)?;
+            println!("Recovered HD wallet {} with {} used addresses into {}", hd_wallet.derive(0).public_key, hd_wallet.address_count, wallet_file.display());
+        }
+        Some(("phrase", _)) => {
+            let hd_wallet = Keystore::load(wallet_file)?.decrypt_hd(password)?;
+            println!("{}", hd_wallet.phrase());
+        }
+        Some(("export", _)) => {
+            let wallet = Keystore::load(wallet_file)?.decrypt(password)?;
+            println!("{}", wallet.secret_key_hex());
+        }
+        Some(("sign", sign_matches)) => {
+            let unsigned_file = sign_matches.get_one::<String>("unsigned").unwrap();
+            let json = fs::read_to_string(unsigned_file).map_err(|e| format!("Cannot read {unsigned_file}: {e}"))?;
+            let unsigned: UnsignedTransaction = serde_json::from_str(&json)?;
+            // shown before signing, the signer cannot check the payment against a chain
+            for output in unsigned.outputs.iter().filter(|output| output.address != unsigned.address) {
+                let recipient = Address::from_public_key_hex(&output.address, unsigned.chain_id)
+                    .map_or_else(|_| output.address.clone(), |address| address.to_string());
+                eprintln!("Pays {} to {recipient}", output.amount);
+            }
+            eprintln!("Fee {} on chain {}", unsigned.fee(), unsigned.chain_id);
+            let wallet = Keystore::load(wallet_file)?.decrypt(password)?;
+            let transaction = unsigned.sign(&wallet)?;
+            println!("{}", serde_json::to_string_pretty(&transaction)?);
+        }
+        _ => unreachable!("clap requires a wallet subcommand"),
+    }
+    Ok(())
+}